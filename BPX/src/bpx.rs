@@ -31,13 +31,16 @@ use std::path::Path;
 use std::vec::Vec;
 use std::io;
 use std::io::Seek;
-use std::io::Write;
-use std::io::Read;
 use std::boxed::Box;
+use std::sync::Arc;
 use byteorder::LittleEndian;
 use byteorder::ByteOrder;
+use rayon::prelude::*;
 use super::garraylen::*;
 use super::section::*;
+use super::progress::ProgressObserver;
+use super::Error;
+use super::Result;
 
 pub const FLAG_COMPRESS_ZLIB: u8 = 0x1;
 pub const FLAG_CHECK_CRC32: u8 = 0x4;
@@ -60,12 +63,19 @@ const SIZE_MAIN_HEADER: usize = 40;
 
 impl BPXPMainHeader
 {
-    fn read<TReader: io::Read>(reader: &mut TReader) -> io::Result<(u32, BPXPMainHeader)>
+    fn read<TReader: io::Read>(reader: &mut TReader) -> Result<(u32, BPXPMainHeader)>
     {
         let mut buf: [u8;SIZE_MAIN_HEADER] = [0;SIZE_MAIN_HEADER];
         let mut checksum: u32 = 0;
 
-        reader.read(&mut buf)?;
+        if let Err(e) = reader.read_exact(&mut buf)
+        {
+            if e.kind() == io::ErrorKind::UnexpectedEof
+            {
+                return Err(Error::Truncated(String::from("main header")));
+            }
+            return Err(Error::from(e));
+        }
         for i in 0..SIZE_MAIN_HEADER
         {
             if i < 4 || i > 7
@@ -84,11 +94,12 @@ impl BPXPMainHeader
         };
         if head.signature[0] != 'B' as u8 || head.signature[1] != 'P' as u8 || head.signature[2] != 'X' as u8
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "[BPX] File is not a BPX: incorrect signature"));
+            return Err(Error::BadSignature);
         }
-        if head.version != 0x1
+        //Version 2 only widens section sizes to 64 bits
+        if head.version != 0x1 && head.version != 0x2
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("[BPX] Unsupported version of BPX: {}", head.version)));
+            return Err(Error::UnsupportedVersion(head.version));
         }
         return Ok((checksum, head));
     }
@@ -145,36 +156,33 @@ impl BPXPMainHeader
     }
 }
 
-pub struct Decoder
+pub struct Decoder<TBackend = File>
 {
     pub main_header: BPXPMainHeader,
     sections: Vec<BPXSectionHeader>,
-    file: File
+    progress: Option<Arc<dyn ProgressObserver>>,
+    file: TBackend
 }
 
-impl Decoder
+impl<TBackend: io::Read + io::Seek> Decoder<TBackend>
 {
-    fn read_section_header_table(&mut self, checksum: u32) -> io::Result<()>
+    fn read_section_header_table(&mut self, checksum: u32) -> Result<()>
     {
         let mut final_checksum = checksum;
 
         for _ in 0..self.main_header.section_num
         {
-            let (checksum, header) = BPXSectionHeader::read(&mut self.file)?;
-            if header.flags & FLAG_COMPRESS_ZLIB == FLAG_COMPRESS_ZLIB
-            {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "[BPX] zlib compression is not supported by FPKG"));
-            }
-            if header.flags & FLAG_CHECK_CRC32 == FLAG_CHECK_CRC32
-            {
-                return Err(io::Error::new(io::ErrorKind::InvalidInput, "[BPX] crc32 checksum is not supported by FPKG"));
-            }
+            let (checksum, header) = BPXSectionHeader::read(&mut self.file, self.main_header.version)?;
             final_checksum += checksum;
             self.sections.push(header);
         }
         if final_checksum != self.main_header.chksum
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "[BPX] checksum validation failed"));
+            return Err(Error::ChecksumMismatch
+            {
+                expected: self.main_header.chksum,
+                actual: final_checksum
+            });
         }
         return Ok(());
     }
@@ -218,60 +226,144 @@ impl Decoder
         return self.sections[index];
     }
 
-    pub fn open_section(&mut self, section: &BPXSectionHeader) -> io::Result<Box<dyn Section>>
+    pub fn open_section(&mut self, section: &BPXSectionHeader) -> Result<Box<dyn Section>>
+    {
+        let data = open_section(&mut self.file, &section)?;
+        if let Some(progress) = &self.progress
+        {
+            progress.section_loaded(section);
+        }
+        return Ok(data);
+    }
+
+    //Returns a reader which decompresses the section on demand instead of loading it all at once
+    pub fn stream_section(&mut self, section: &BPXSectionHeader) -> Result<SectionReader<'_, TBackend>>
     {
-        return open_section(&mut self.file, &section);
+        return stream_section(&mut self.file, &section);
     }
 
-    pub fn load_string_section(&mut self) -> io::Result<Box<dyn Section>>
+    pub fn load_string_section(&mut self) -> Result<Box<dyn Section>>
     {
         if let Some(section) = self.find_section_by_type(STRING_SECTION_TYPE)
         {
             return self.open_section(&section);
         }
-        return Err(io::Error::new(io::ErrorKind::InvalidInput, "[BPX] could not locate string section"));
+        return Err(Error::MissingSection("string"));
     }
 
-    pub fn new(file: &Path) -> io::Result<Decoder>
+    pub fn from_reader(mut reader: TBackend) -> Result<Decoder<TBackend>>
     {
-        let mut fle = File::open(file)?;
-        let (checksum, header) = BPXPMainHeader::read(&mut fle)?;
+        let (checksum, header) = BPXPMainHeader::read(&mut reader)?;
         let num = header.section_num;
         let mut decoder = Decoder
         {
-            file: fle,
+            file: reader,
             main_header: header,
-            sections: Vec::with_capacity(num as usize)
+            sections: Vec::with_capacity(num as usize),
+            progress: None
         };
         decoder.read_section_header_table(checksum)?;
         return Ok(decoder);
     }
+
+    pub fn set_progress(&mut self, progress: Arc<dyn ProgressObserver>)
+    {
+        self.progress = Some(progress);
+    }
+
+    pub fn into_inner(self) -> TBackend
+    {
+        return self.file;
+    }
+}
+
+impl Decoder<File>
+{
+    pub fn new(file: &Path) -> Result<Decoder>
+    {
+        let fle = File::open(file)?;
+        return Decoder::from_reader(fle);
+    }
+}
+
+#[derive(Copy, Clone)]
+struct SectionOptions
+{
+    compression: CompressionPolicy,
+    checksum: ChecksumMethod
+}
+
+//Sections are compressed in parallel into memory as long as the ones in flight stay below this size
+//Bigger sections are compressed one at a time directly into the output
+const MAX_BUFFERED_SIZE: usize = 64 * 1024 * 1024;
+
+fn compress_section(header: &mut BPXSectionHeader, data: &mut Box<dyn Section>, options: &SectionOptions, out: &mut dyn io::Write, index: usize, progress: Option<&dyn ProgressObserver>) -> Result<()>
+{
+    data.seek(io::SeekFrom::Start(0))?;
+    let (csize, chksum, flags) = write_section(data, out, &options.compression, options.checksum, progress)?;
+    header.csize = csize as u64;
+    header.size = data.size() as u64;
+    header.chksum = chksum;
+    header.flags = flags;
+    if let Some(progress) = progress
+    {
+        progress.section_written(index, header);
+    }
+    return Ok(());
 }
 
-pub struct Encoder
+pub struct Encoder<TBackend = File>
 {
     pub main_header: BPXPMainHeader,
     sections: Vec<BPXSectionHeader>,
     sections_data: Vec<Box<dyn Section>>,
-    file: File
+    options: Vec<SectionOptions>,
+    policy: CompressionPolicy,
+    checksum: ChecksumMethod,
+    min_version: u32,
+    progress: Option<Arc<dyn ProgressObserver>>,
+    file: TBackend
 }
 
-impl Encoder
+impl Encoder<File>
 {
-    pub fn new(file: &Path) -> io::Result<Encoder>
+    pub fn new(file: &Path) -> Result<Encoder>
     {
         let fle = File::create(file)?;
-        return Ok(Encoder
+        return Ok(Encoder::from_writer(fle));
+    }
+}
+
+impl<TBackend: io::Write + io::Seek> Encoder<TBackend>
+{
+    pub fn from_writer(writer: TBackend) -> Encoder<TBackend>
+    {
+        return Encoder
         {
             main_header: BPXPMainHeader::new(),
             sections: Vec::new(),
             sections_data: Vec::new(),
-            file: fle
-        });
+            options: Vec::new(),
+            policy: CompressionPolicy::new(CompressionMethod::Xz),
+            checksum: ChecksumMethod::Weak,
+            min_version: 0x1,
+            progress: None,
+            file: writer
+        };
+    }
+
+    pub fn set_progress(&mut self, progress: Arc<dyn ProgressObserver>)
+    {
+        self.progress = Some(progress);
+    }
+
+    pub fn into_inner(self) -> TBackend
+    {
+        return self.file;
     }
 
     //Adds a new section; returns a reference to the new section for use in edit_section
-    pub fn add_section(&mut self, btype: u8, size: u32 /* use 0 for automatic size */) -> io::Result<usize>
+    pub fn add_section(&mut self, btype: u8, size: u64 /* use 0 for automatic size */) -> Result<usize>
     {
         self.main_header.section_num += 1;
         let header = BPXSectionHeader::new(size, btype);
@@ -279,9 +371,66 @@ impl Encoder
         self.sections.push(header);
         let r = self.sections.len() - 1;
         self.sections_data.push(section);
+        self.options.push(SectionOptions
+        {
+            compression: self.policy,
+            checksum: self.checksum
+        });
         return Ok(r);
     }
 
+    //Selects the codec used if the section is large enough to be compressed (default: XZ)
+    pub fn set_section_compression(&mut self, index: usize, method: CompressionMethod)
+    {
+        self.options[index].compression.method = method;
+    }
+
+    pub fn set_section_policy(&mut self, index: usize, policy: CompressionPolicy) -> Result<()>
+    {
+        policy.validate()?;
+        self.options[index].compression = policy;
+        return Ok(());
+    }
+
+    //Applies the compression policy to all existing sections and to sections added later on
+    pub fn set_compression_policy(&mut self, policy: CompressionPolicy) -> Result<()>
+    {
+        policy.validate()?;
+        self.policy = policy;
+        for v in &mut self.options
+        {
+            v.compression = policy;
+        }
+        return Ok(());
+    }
+
+    //Selects the checksum stored in the section header (default: weak)
+    pub fn set_section_checksum(&mut self, index: usize, method: ChecksumMethod)
+    {
+        self.options[index].checksum = method;
+    }
+
+    //Applies the checksum method to all existing sections and to sections added later on
+    pub fn set_checksum_method(&mut self, method: ChecksumMethod)
+    {
+        self.checksum = method;
+        for v in &mut self.options
+        {
+            v.checksum = method;
+        }
+    }
+
+    //Selects the oldest BPX version to emit (default: 1); version 2 is still used when a section needs it
+    pub fn set_minimum_version(&mut self, version: u32) -> Result<()>
+    {
+        if version != 0x1 && version != 0x2
+        {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        self.min_version = version;
+        return Ok(());
+    }
+
     pub fn find_section_by_type(&mut self, btype: u8) -> Option<usize>
     {
         for i in 0..self.sections.len()
@@ -299,61 +448,99 @@ impl Encoder
         return &mut self.sections_data[index];
     }
 
-    fn write_compress_sections(&mut self) -> io::Result<(File, u32, usize)>
+    //Compresses all sections and writes them in order to the output
+    fn write_compress_sections(&mut self) -> Result<()>
     {
-        let mut all_sections_size: usize = 0;
-        let mut chksum_sht: u32 = 0;
-        let mut ptr: u64 = SIZE_MAIN_HEADER as u64 + (self.sections.len() as u64 * SIZE_SECTION_HEADER as u64);
-        let mut f = tempfile::tempfile()?;
+        let progress = self.progress.as_deref();
+        let mut i = 0;
 
-        for i in 0..self.sections.len()
+        while i < self.sections.len()
         {
-            if self.sections_data[i].size() > u32::MAX as usize
+            if self.sections_data[i].size() > MAX_BUFFERED_SIZE
+            {
+                compress_section(&mut self.sections[i], &mut self.sections_data[i], &self.options[i], &mut self.file, i, progress)?;
+                i += 1;
+                continue;
+            }
+            //Sections are independent so compress the next ones on the thread pool, then write them in order
+            //Progress is reported from the worker threads as soon as each section is done
+            let mut end = i;
+            let mut buffered = 0;
+            while end < self.sections.len() && buffered + self.sections_data[end].size() <= MAX_BUFFERED_SIZE
+            {
+                buffered += self.sections_data[end].size();
+                end += 1;
+            }
+            let results: Vec<Result<Vec<u8>>> = self.sections[i..end].par_iter_mut().zip(self.sections_data[i..end].par_iter_mut()).zip(self.options[i..end].par_iter()).enumerate().map(|(j, ((header, data), options))|
+            {
+                let mut buf = Vec::new();
+                compress_section(header, data, options, &mut buf, i + j, progress)?;
+                return Ok(buf);
+            }).collect();
+            for res in results
             {
-                panic!("BPX cannot support individual sections with size exceeding 4Gb (2 pow 32)");
+                self.file.write_all(&res?)?;
             }
-            self.sections_data[i].seek(io::SeekFrom::Start(0))?;
-            let (csize, chksum, flags) = write_section(&mut self.sections_data[i], &mut f)?;
-            self.sections[i].csize = csize as u32;
-            self.sections[i].size = self.sections_data[i].size() as u32;
-            self.sections[i].chksum = chksum;
-            self.sections[i].flags = flags;
-            self.sections[i].pointer = ptr;
-            println!("Writing section #{}: Size = {}, Size after compression = {}", i, self.sections[i].size, self.sections[i].csize);
-            ptr += csize as u64;
-            chksum_sht += self.sections[i].get_checksum();
-            all_sections_size += csize;
+            i = end;
         }
-        return Ok((f, chksum_sht, all_sections_size));
+        return Ok(());
+    }
+
+    //Only emit BPX v2 when a section does not fit in a v1 header so that older decoders can still read most files
+    fn get_version(&self) -> u32
+    {
+        if self.sections.iter().zip(self.sections_data.iter()).any(|(header, data)| header.needs_v2() || data.size() > u32::MAX as usize)
+        {
+            return 0x2;
+        }
+        return self.min_version;
     }
 
-    fn write_data_file(&mut self, fle: &mut File, all_sections_size: usize) -> io::Result<()>
+    //Section pointers can only be computed once all sizes are known as they decide of the BPX version
+    fn write_section_pointers(&mut self) -> u32
     {
-        let mut idata: [u8; 8192] = [0; 8192];
-        let mut count: usize = 0;
+        let mut chksum_sht: u32 = 0;
+        let version = self.main_header.version;
+        let mut ptr: u64 = SIZE_MAIN_HEADER as u64 + (self.sections.len() * get_section_header_size(version)) as u64;
 
-        fle.seek(io::SeekFrom::Start(0))?;
-        while count < all_sections_size
+        for v in &mut self.sections
         {
-            let res = fle.read(&mut idata)?;
-            self.file.write(&idata[0..res])?;
-            count += res;
+            v.pointer = ptr;
+            ptr += v.csize;
+            chksum_sht += v.get_checksum(version);
         }
-        return Ok(());
+        self.main_header.file_size = ptr;
+        return chksum_sht;
     }
 
-    pub fn save(&mut self) -> io::Result<()>
+    pub fn save(&mut self) -> Result<()>
     {
-        let (mut main_data, chksum_sht, all_sections_size) = self.write_compress_sections()?;
+        let start = self.file.stream_position()?;
+        let mut version = self.get_version();
 
-        self.main_header.file_size = all_sections_size as u64 + (self.sections.len() * SIZE_SECTION_HEADER) as u64 + SIZE_MAIN_HEADER as u64;
+        //Section data is written right after the headers; their size depends on the version which depends on the compressed sizes
+        //These are only known once written so start again in the (unlikely) case a compressed size no longer fits a v1 header
+        loop
+        {
+            self.file.seek(io::SeekFrom::Start(start + (SIZE_MAIN_HEADER + self.sections.len() * get_section_header_size(version)) as u64))?;
+            self.write_compress_sections()?;
+            let needed = self.get_version();
+            if get_section_header_size(needed) == get_section_header_size(version)
+            {
+                break;
+            }
+            version = needed;
+        }
+        self.main_header.version = version;
+        let chksum_sht = self.write_section_pointers();
         self.main_header.chksum = chksum_sht + self.main_header.get_checksum();
+        self.file.seek(io::SeekFrom::Start(start))?;
         self.main_header.write(&mut self.file)?;
         for v in &self.sections
         {
-            v.write(&mut self.file)?;
+            v.write(&mut self.file, self.main_header.version)?;
         }
-        self.write_data_file(&mut main_data, all_sections_size)?;
+        self.file.seek(io::SeekFrom::Start(start + self.main_header.file_size))?;
         return Ok(());
     }
 }
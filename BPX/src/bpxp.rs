@@ -29,25 +29,48 @@
 use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
-use std::vec::Vec;
 use std::io;
 use std::io::Write;
 use std::io::Read;
+use std::sync::Arc;
 use byteorder::LittleEndian;
 use byteorder::ByteOrder;
 use super::strings::*;
 use std::fs::metadata;
 use std::fs::read_dir;
 use super::bpx;
+use super::section::BPXSectionHeader;
+use super::section::CompressionPolicy;
+use super::section::ChecksumMethod;
+use super::section::SectionReader;
+use super::section::stream_section;
+use super::utils::glob_match;
 use super::sd::Object;
 use super::sd::load_structured_data;
 use super::sd::write_structured_data;
+use super::progress::ProgressObserver;
+use super::Error;
+use super::Result;
 
 const DATA_SECTION_TYPE: u8 = 0x1;
 
 const DATA_WRITE_BUFFER_SIZE: usize = 8192;
 const MIN_DATA_REMAINING_SIZE: usize = DATA_WRITE_BUFFER_SIZE;
 const MAX_DATA_SECTION_SIZE: usize = 200000000 - MIN_DATA_REMAINING_SIZE; //200MB
+const MAX_LINK_TARGET_SIZE: u64 = 4096;
+
+//Version of the per-entry header stored in type_ext[4]
+//0: size (u64) + name (u32)
+//1: size (u64) + name (u32) + mode (u32)
+//2: size (u64) + name (u32) + mode (u32) + mtime (u64)
+const ENTRY_FORMAT_VERSION: u8 = 2;
+
+//Unix style entry kind bits stored in the mode field
+const MODE_KIND_MASK: u32 = 0o170000;
+const MODE_FILE: u32 = 0o100000;
+const MODE_SYMLINK: u32 = 0o120000;
+const MODE_DIRECTORY: u32 = 0o040000;
+const MODE_PERMISSIONS_MASK: u32 = 0o777; //setuid, setgid and sticky bits are never stored nor restored
 
 pub enum Architecture
 {
@@ -67,14 +90,292 @@ pub enum Platform
     Any
 }
 
-pub struct Decoder
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntryKind
+{
+    File,
+    Symlink, //The content of the entry is the link target
+    Directory
+}
+
+#[derive(Clone)]
+pub struct TocEntry
+{
+    pub path: String,
+    pub kind: EntryKind,
+    pub mode: u32, //Unix permission bits; 0 when unknown
+    pub mtime: u64, //Modification time in seconds since the Unix epoch; 0 when unknown
+    pub size: u64,
+    pub sections: Vec<usize>, //Indices of the data sections holding the file content, in order
+    pub offset: u64 //Offset of the file content in the first data section
+}
+
+//Raised when unpacking a packed file whose path could escape the target directory
+#[derive(Debug)]
+pub struct UnsafePathError
+{
+    pub path: String,
+    pub reason: &'static str
+}
+
+impl std::fmt::Display for UnsafePathError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        return write!(f, "[BPX] unsafe packed file path {:?}: {}", self.path, self.reason);
+    }
+}
+
+impl std::error::Error for UnsafePathError {}
+
+fn unsafe_path(path: &str, reason: &'static str) -> Error
+{
+    return Error::UnsafePath(UnsafePathError
+    {
+        path: String::from(path),
+        reason: reason
+    });
+}
+
+//Converts a packed file path to a relative host path which cannot escape the target directory
+pub fn sanitize_path(path: &str) -> Result<PathBuf>
+{
+    if path.contains('\0')
+    {
+        return Err(unsafe_path(path, "path contains a NUL byte"));
+    }
+    if path.starts_with('/') || path.starts_with('\\')
+    {
+        return Err(unsafe_path(path, "absolute paths are not allowed"));
+    }
+    let mut res = PathBuf::new();
+    for component in path.split(['/', '\\'])
+    {
+        if component == "" || component == "."
+        {
+            continue;
+        }
+        if component == ".."
+        {
+            return Err(unsafe_path(path, "parent directory components are not allowed"));
+        }
+        if component.contains(':')
+        {
+            return Err(unsafe_path(path, "drive prefixes and stream names are not allowed"));
+        }
+        res.push(component);
+    }
+    if res.as_os_str().is_empty()
+    {
+        return Err(unsafe_path(path, "path is empty"));
+    }
+    return Ok(res);
+}
+
+struct EntryHeader
+{
+    size: u64,
+    name: u32,
+    kind: EntryKind,
+    mode: u32,
+    mtime: u64
+}
+
+fn entry_header_size(version: u8) -> u64
+{
+    return match version
+    {
+        0 => 12,
+        1 => 16,
+        _ => 24
+    };
+}
+
+fn read_entry_header(source: &mut dyn Read, version: u8) -> Result<EntryHeader>
+{
+    let mut buf: [u8; 24] = [0; 24];
+    if let Err(e) = source.read_exact(&mut buf[0..entry_header_size(version) as usize])
+    {
+        if e.kind() == io::ErrorKind::UnexpectedEof
+        {
+            return Err(Error::Truncated(String::from("packed entry header")));
+        }
+        return Err(Error::from(e));
+    }
+    let mut header = EntryHeader
+    {
+        size: LittleEndian::read_u64(&buf[0..8]),
+        name: LittleEndian::read_u32(&buf[8..12]),
+        kind: EntryKind::File,
+        mode: 0,
+        mtime: 0
+    };
+    if version >= 1
+    {
+        let mode = LittleEndian::read_u32(&buf[12..16]);
+        header.mode = mode & MODE_PERMISSIONS_MASK;
+        header.kind = match mode & MODE_KIND_MASK
+        {
+            MODE_FILE => EntryKind::File,
+            MODE_SYMLINK => EntryKind::Symlink,
+            MODE_DIRECTORY => EntryKind::Directory,
+            _ => return Err(Error::UnknownEntryKind(mode & MODE_KIND_MASK))
+        };
+    }
+    if version >= 2
+    {
+        header.mtime = LittleEndian::read_u64(&buf[16..24]);
+    }
+    return Ok(header);
+}
+
+//Checks that a symbolic link stored at path (already sanitized) cannot point outside of the target directory
+//The check is lexical so going up from another symbolic link of the package (links) is rejected as it could resolve anywhere
+fn check_link_target(path: &Path, target: &str, links: &[(PathBuf, String)]) -> Result<()>
+{
+    if target.contains('\0') || target.starts_with('/') || target.starts_with('\\') || target.contains(':')
+    {
+        return Err(unsafe_path(target, "symbolic link target must be a relative path"));
+    }
+    let mut current = match path.parent()
+    {
+        Some(v) => v.to_path_buf(),
+        None => PathBuf::new()
+    };
+    for component in target.split(['/', '\\'])
+    {
+        match component
+        {
+            "" | "." => continue,
+            ".." =>
+            {
+                if links.iter().any(|(v, _)| current.starts_with(v))
+                {
+                    return Err(unsafe_path(target, "symbolic link target goes up through another symbolic link"));
+                }
+                if !current.pop()
+                {
+                    return Err(unsafe_path(target, "symbolic link target escapes the target directory"));
+                }
+            },
+            _ => current.push(component)
+        };
+    }
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn get_permissions(md: &std::fs::Metadata) -> u32
+{
+    use std::os::unix::fs::PermissionsExt;
+    return md.permissions().mode() & MODE_PERMISSIONS_MASK;
+}
+
+#[cfg(not(unix))]
+fn get_permissions(_: &std::fs::Metadata) -> u32
+{
+    return 0;
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: u32) -> io::Result<()>
+{
+    use std::os::unix::fs::PermissionsExt;
+    if mode != 0
+    {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_: &Path, _: u32) -> io::Result<()>
+{
+    return Ok(());
+}
+
+fn get_mtime(md: &std::fs::Metadata) -> u64
+{
+    return match md.modified().map(|v| v.duration_since(std::time::UNIX_EPOCH))
+    {
+        Ok(Ok(v)) => v.as_secs(),
+        _ => 0
+    };
+}
+
+fn set_mtime(file: &File, mtime: u64) -> io::Result<()>
+{
+    if mtime != 0
+    {
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime))?;
+    }
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn set_directory_mtime(path: &Path, mtime: u64) -> io::Result<()>
+{
+    return set_mtime(&File::open(path)?, mtime);
+}
+
+#[cfg(not(unix))]
+fn set_directory_mtime(_: &Path, _: u64) -> io::Result<()>
+{
+    return Ok(());
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, dest: &Path) -> io::Result<()>
+{
+    return std::os::unix::fs::symlink(target, dest);
+}
+
+#[cfg(not(unix))]
+fn create_symlink(target: &str, dest: &Path) -> io::Result<()>
+{
+    //Symbolic links are not portable; store the link target as a regular file instead
+    return std::fs::write(dest, target);
+}
+
+//A packed file being extracted; out is None when the file is filtered out
+struct PendingFile
+{
+    out: Option<File>,
+    mtime: u64
+}
+
+impl PendingFile
+{
+    fn write_data(&mut self, source: &mut dyn Read, size: u64, progress: Option<&dyn ProgressObserver>) -> io::Result<u64>
+    {
+        return match &mut self.out
+        {
+            Some(file) => copy_file_data(source, file, size, progress),
+            None => copy_file_data(source, &mut io::sink(), size, None)
+        };
+    }
+
+    fn finish(self) -> io::Result<()>
+    {
+        if let Some(file) = self.out
+        {
+            set_mtime(&file, self.mtime)?;
+        }
+        return Ok(());
+    }
+}
+
+pub struct Decoder<TBackend = File>
 {
     pub architecture: Architecture,
     pub platform: Platform,
-    decoder: bpx::Decoder
+    version: u8,
+    progress: Option<Arc<dyn ProgressObserver>>,
+    toc: Option<Vec<TocEntry>>, //Cached by the first call to table_of_contents or open_file
+    decoder: bpx::Decoder<TBackend>
 }
 
-fn get_arch_platform_from_code(acode: u8, pcode: u8) -> io::Result<(Architecture, Platform)>
+fn get_arch_platform_from_code(acode: u8, pcode: u8) -> Result<(Architecture, Platform)>
 {
     let arch;
     let platform;
@@ -86,7 +387,7 @@ fn get_arch_platform_from_code(acode: u8, pcode: u8) -> io::Result<(Architecture
         0x2 => arch = Architecture::X86,
         0x3 => arch = Architecture::Armv7hl,
         0x4 => arch = Architecture::Any,
-        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "[BPX] Architecture code does not exist"))
+        _ => return Err(Error::UnknownArchitecture(acode))
     }
     match pcode
     {
@@ -95,149 +396,419 @@ fn get_arch_platform_from_code(acode: u8, pcode: u8) -> io::Result<(Architecture
         0x2 => platform = Platform::Windows,
         0x3 => platform = Platform::Android,
         0x4 => platform = Platform::Any,
-        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "[BPX] Platform code does not exist"))
+        _ => return Err(Error::UnknownPlatform(pcode))
     }
     return Ok((arch, platform));
 }
 
-impl Decoder
+//Copies up to size bytes; returns the number of bytes which could not be copied because the source ended first
+fn copy_file_data(source: &mut dyn Read, out: &mut dyn Write, size: u64, progress: Option<&dyn ProgressObserver>) -> io::Result<u64>
 {
-    pub fn new(file: &Path) -> io::Result<Decoder>
+    let mut buf: [u8; DATA_WRITE_BUFFER_SIZE] = [0; DATA_WRITE_BUFFER_SIZE];
+    let mut count: u64 = 0;
+    while count < size
+    {
+        let len = std::cmp::min(DATA_WRITE_BUFFER_SIZE as u64, size - count) as usize;
+        let res = source.read(&mut buf[0..len])?;
+        if res == 0
+        { //Well the file is divided in multiple sections signal the caller of the problen
+            return Ok(size - count);
+        }
+        out.write_all(&buf[0..res])?;
+        if let Some(progress) = progress
+        {
+            progress.bytes_processed(res as u64);
+        }
+        count += res as u64;
+    }
+    return Ok(0);
+}
+
+fn create_file(dest: &Path) -> io::Result<File>
+{
+    if let Some(v) = dest.parent()
+    {
+        std::fs::create_dir_all(v)?;
+    }
+    return File::create(dest);
+}
+
+//Reads a single packed file, following it across data sections
+pub struct FileReader<'a, TBackend: io::Read + io::Seek>
+{
+    reader: Option<SectionReader<'a, TBackend>>,
+    sections: Vec<BPXSectionHeader>, //Next sections to read in reverse order
+    remaining: u64
+}
+
+impl<'a, TBackend: io::Read + io::Seek> io::Read for FileReader<'a, TBackend>
+{
+    fn read(&mut self, data: &mut [u8]) -> io::Result<usize>
+    {
+        let len = std::cmp::min(data.len() as u64, self.remaining) as usize;
+        if len == 0
+        {
+            return Ok(0);
+        }
+        loop
+        {
+            let res = match &mut self.reader
+            {
+                Some(reader) => reader.read(&mut data[0..len])?,
+                None => return Ok(0)
+            };
+            if res > 0
+            {
+                self.remaining -= res as u64;
+                return Ok(res);
+            }
+            let next = match self.sections.pop()
+            {
+                Some(v) => v,
+                None => return Err(io::Error::from(Error::Truncated(String::from("packed file"))))
+            };
+            if let Some(reader) = self.reader.take()
+            {
+                self.reader = Some(stream_section(reader.into_inner(), &next)?);
+            }
+        }
+    }
+}
+
+impl Decoder<File>
+{
+    pub fn new(file: &Path) -> Result<Decoder>
     {
         let decoder = bpx::Decoder::new(file)?;
+        return Decoder::from_bpx(decoder);
+    }
+}
+
+impl<TBackend: io::Read + io::Seek> Decoder<TBackend>
+{
+    pub fn from_reader(reader: TBackend) -> Result<Decoder<TBackend>>
+    {
+        let decoder = bpx::Decoder::from_reader(reader)?;
+        return Decoder::from_bpx(decoder);
+    }
+
+    fn from_bpx(decoder: bpx::Decoder<TBackend>) -> Result<Decoder<TBackend>>
+    {
         if decoder.main_header.btype != 'P' as u8
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] Unknown type of BPX: {}", decoder.main_header.btype as char)));
+            return Err(Error::UnsupportedType(decoder.main_header.btype));
         }
         let (a, p) = get_arch_platform_from_code(decoder.main_header.type_ext[0], decoder.main_header.type_ext[1])?;
         if decoder.main_header.type_ext[2] != 0x50 || decoder.main_header.type_ext[3] != 0x4B
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] Unsupported BPXP variant {}{}", decoder.main_header.type_ext[2] as char, decoder.main_header.type_ext[3] as char)));
+            return Err(Error::UnsupportedVariant(decoder.main_header.type_ext[2], decoder.main_header.type_ext[3]));
+        }
+        let version = decoder.main_header.type_ext[4];
+        if version > ENTRY_FORMAT_VERSION
+        {
+            return Err(Error::UnsupportedEntryFormat(version));
         }
         return Ok(Decoder
         {
             architecture: a,
             platform: p,
+            version: version,
+            progress: None,
+            toc: None,
             decoder: decoder
         })
     }
 
-    pub fn open_metadata(&mut self) -> io::Result<Object>
+    //Reports extracted entries as well as section events of the underlying BPX
+    pub fn set_progress(&mut self, progress: Arc<dyn ProgressObserver>)
+    {
+        self.decoder.set_progress(progress.clone());
+        self.progress = Some(progress);
+    }
+
+    pub fn open_metadata(&mut self) -> Result<Object>
     {
         if let Some(section) = self.decoder.find_section_by_type(254)
         {
             let mut data = self.decoder.open_section(&section)?;
             return load_structured_data(&mut data);
         }
-        return Err(io::Error::new(io::ErrorKind::InvalidInput, "[BPX] could not locate metadata section"));
+        return Err(Error::MissingSection("metadata"));
     }
 
-    fn extract_file(&self, source: &mut dyn Read, dest: &PathBuf, size: u64) -> io::Result<Option<(u64, File)>>
+    fn find_data_sections(&self) -> Vec<usize>
     {
-        if let Some(v) = dest.parent()
+        let mut res = Vec::new();
+        for i in 0..self.decoder.main_header.section_num as usize
         {
-            std::fs::create_dir_all(v)?;
-        }
-        let mut fle = File::create(dest)?;
-        let mut v: Vec<u8> = Vec::with_capacity(DATA_WRITE_BUFFER_SIZE);
-        let mut count: u64 = 0;
-        while count < size
-        {
-            let mut byte: [u8; 1] = [0; 1];
-            if source.read(&mut byte)? == 0 && count < size
-            { //Well the file is divided in multiple sections signal the caller of the problen
-                fle.write(&v)?;
-                return Ok(Some((size - count, fle)));
-            }
-            v.push(byte[0]);
-            if v.len() >= DATA_WRITE_BUFFER_SIZE
+            if self.decoder.get_section_by_index(i).btype == DATA_SECTION_TYPE
             {
-                fle.write(&v)?;
-                v = Vec::with_capacity(DATA_WRITE_BUFFER_SIZE);
+                res.push(i);
             }
-            count += 1;
         }
-        fle.write(&v)?;
-        return Ok(None);
+        return res;
+    }
+
+    //Lists all packed files without extracting them
+    pub fn table_of_contents(&mut self) -> Result<Vec<TocEntry>>
+    {
+        return Ok(self.load_table_of_contents()?.clone());
+    }
+
+    fn load_table_of_contents(&mut self) -> Result<&Vec<TocEntry>>
+    {
+        if self.toc.is_none()
+        {
+            self.toc = Some(self.read_table_of_contents()?);
+        }
+        return Ok(self.toc.as_ref().unwrap());
     }
 
-    fn continue_file(&self, source: &mut dyn Read, out: &mut dyn Write, size: u64) -> io::Result<u64>
+    fn read_table_of_contents(&mut self) -> Result<Vec<TocEntry>>
     {
-        let mut v: Vec<u8> = Vec::with_capacity(DATA_WRITE_BUFFER_SIZE);
-        let mut count: u64 = 0;
-        while count < size
+        let mut strings = self.decoder.load_string_section()?;
+        let mut entries: Vec<TocEntry> = Vec::new();
+        let mut truncated: u64 = 0;
+        for index in self.find_data_sections()
         {
-            let mut byte: [u8; 1] = [0; 1];
-            if source.read(&mut byte)? == 0 && count < size
-            { //Well the file is divided in multiple sections signal the caller of the problen
-                out.write(&v)?;
-                return Ok(size - count);
+            let header = self.decoder.get_section_by_index(index);
+            let mut section = self.decoder.stream_section(&header)?;
+            let mut count: u64 = 0;
+            if truncated > 0
+            {
+                if let Some(entry) = entries.last_mut()
+                {
+                    entry.sections.push(index);
+                }
+                let res = copy_file_data(&mut section, &mut io::sink(), truncated, None)?;
+                if res > 0 //Still not finished
+                {
+                    truncated = res;
+                    continue;
+                }
+                count = truncated;
+                truncated = 0;
             }
-            v.push(byte[0]);
-            if v.len() >= DATA_WRITE_BUFFER_SIZE
+            while count < header.size
             {
-                out.write(&v)?;
-                v = Vec::with_capacity(DATA_WRITE_BUFFER_SIZE);
+                let entry = read_entry_header(&mut section, self.version)?;
+                let header_size = entry_header_size(self.version);
+                entries.push(TocEntry
+                {
+                    path: get_string(entry.name, &mut strings)?,
+                    kind: entry.kind,
+                    mode: entry.mode,
+                    mtime: entry.mtime,
+                    size: entry.size,
+                    sections: vec!(index),
+                    offset: count + header_size
+                });
+                truncated = copy_file_data(&mut section, &mut io::sink(), entry.size, None)?;
+                if truncated > 0
+                {
+                    break;
+                }
+                count += entry.size + header_size;
             }
-            count += 1;
         }
-        return Ok(0);
+        if truncated > 0
+        {
+            if let Some(entry) = entries.last()
+            {
+                return Err(Error::Truncated(format!("file entry {}", entry.path)));
+            }
+        }
+        return Ok(entries);
     }
 
-    pub fn unpack(&mut self, target: &Path) -> io::Result<()>
+    //Opens a single packed file for reading without extracting anything
+    pub fn open_file(&mut self, path: &str) -> Result<FileReader<'_, TBackend>>
     {
+        //The table of contents is only read once so that opening many files does not scan the package each time
+        let entry = match self.load_table_of_contents()?.iter().find(|v| v.path == path)
+        {
+            Some(v) => v.clone(),
+            None => return Err(Error::NotFound(String::from(path)))
+        };
+        let mut sections: Vec<BPXSectionHeader> = entry.sections.iter().rev().map(|v| self.decoder.get_section_by_index(*v)).collect();
+        let first = match sections.pop()
+        {
+            Some(v) => v,
+            None => return Err(Error::Corrupted(format!("packed file {} has no data section", path)))
+        };
+        let mut reader = self.decoder.stream_section(&first)?;
+        if copy_file_data(&mut reader, &mut io::sink(), entry.offset, None)? > 0
+        {
+            return Err(Error::Truncated(format!("packed file {}", path)));
+        }
+        return Ok(FileReader
+        {
+            reader: Some(reader),
+            sections: sections,
+            remaining: entry.size
+        });
+    }
+
+    fn unpack_filtered(&mut self, target: &Path, filter: &dyn Fn(&str) -> bool) -> Result<()>
+    {
+        let observer = self.progress.clone();
+        let progress = observer.as_deref();
         let mut strings = self.decoder.load_string_section()?;
         let secs = self.decoder.find_all_sections_of_type(DATA_SECTION_TYPE);
-        let mut truncated: Option<(u64, File)> = None;
+        let mut truncated: Option<(u64, PendingFile, String)> = None;
+        let mut links: Vec<(PathBuf, String)> = Vec::new();
+        let mut directories: Vec<(PathBuf, EntryHeader)> = Vec::new();
         for v in secs
         {
-            let mut section = self.decoder.open_section(&v)?;
-            if let Some((remaining, mut file)) = std::mem::replace(&mut truncated, None)
+            let mut section = self.decoder.stream_section(&v)?;
+            let mut count: u64 = 0;
+            if let Some((remaining, mut pending, path)) = std::mem::replace(&mut truncated, None)
             {
-                let res = self.continue_file(&mut section, &mut file, remaining)?;
+                let res = pending.write_data(&mut section, remaining, progress)?;
                 if res > 0 //Still not finished
                 {
-                    truncated = Some((res, file));
+                    truncated = Some((res, pending, path));
                     continue;
                 }
+                pending.finish()?;
+                count = remaining;
             }
-            let mut count: u64 = 0;
-            while count < v.size as u64
+            while count < v.size
             {
-                let mut header: [u8; 12] = [0; 12];
-                section.read(&mut header)?;
-                let path = get_string(LittleEndian::read_u32(&header[8..12]), &mut strings)?;
-                if path == ""
+                let entry = read_entry_header(&mut section, self.version)?;
+                let path = get_string(entry.name, &mut strings)?;
+                let relative = sanitize_path(&path)?;
+                let size = entry.size;
+                count += size + entry_header_size(self.version);
+                if !filter(&path)
                 {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "[BPX] Empty path string detected, aborting to prevent damage on host files"));
+                    let remaining = copy_file_data(&mut section, &mut io::sink(), size, None)?;
+                    if remaining > 0
+                    {
+                        truncated = Some((remaining, PendingFile { out: None, mtime: 0 }, path));
+                        break;
+                    }
+                    continue;
                 }
-                let size = LittleEndian::read_u64(&header[0..8]);
-                println!("Reading {} with {} byte(s)...", path, size);
-                let mut dest = PathBuf::new();
-                dest.push(target);
-                dest.push(path);
-                truncated = self.extract_file(&mut section, &dest, size)?;
-                if truncated.is_some()
+                //Never write through a symbolic link extracted from this package
+                if links.iter().any(|(v, _)| relative.starts_with(v))
                 {
-                    break;
+                    return Err(unsafe_path(&path, "path goes through a symbolic link"));
                 }
-                count += size + 12;
+                if let Some(progress) = progress
+                {
+                    progress.unpacking_entry(&path, entry.kind, size);
+                }
+                let dest = target.join(&relative);
+                match entry.kind
+                {
+                    EntryKind::File =>
+                    {
+                        let file = create_file(&dest)?;
+                        set_permissions(&dest, entry.mode)?;
+                        let mut pending = PendingFile
+                        {
+                            out: Some(file),
+                            mtime: entry.mtime
+                        };
+                        let remaining = pending.write_data(&mut section, size, progress)?;
+                        if remaining > 0
+                        {
+                            truncated = Some((remaining, pending, path));
+                            break;
+                        }
+                        pending.finish()?;
+                    },
+                    EntryKind::Symlink =>
+                    {
+                        if size > MAX_LINK_TARGET_SIZE
+                        {
+                            return Err(Error::Corrupted(format!("symbolic link target of {} is too long", path)));
+                        }
+                        let mut buf: Vec<u8> = Vec::new();
+                        if copy_file_data(&mut section, &mut buf, size, progress)? > 0
+                        {
+                            return Err(Error::Truncated(format!("symbolic link entry {}", path)));
+                        }
+                        let link = match String::from_utf8(buf)
+                        {
+                            Ok(v) => v,
+                            Err(e) => return Err(Error::Utf8(e))
+                        };
+                        //A new link can change where the links extracted before it point to so check them all again
+                        links.push((relative.clone(), link.clone()));
+                        for (path, target) in &links
+                        {
+                            check_link_target(path, target, &links)?;
+                        }
+                        if let Some(parent) = dest.parent()
+                        {
+                            std::fs::create_dir_all(parent)?;
+                        }
+                        if std::fs::symlink_metadata(&dest).is_ok()
+                        {
+                            std::fs::remove_file(&dest)?;
+                        }
+                        create_symlink(&link, &dest)?;
+                    },
+                    EntryKind::Directory =>
+                    {
+                        std::fs::create_dir_all(&dest)?;
+                        if copy_file_data(&mut section, &mut io::sink(), size, None)? > 0
+                        {
+                            return Err(Error::Truncated(format!("directory entry {}", path)));
+                        }
+                        directories.push((dest, entry));
+                    }
+                };
             }
         }
+        if let Some((_, _, path)) = truncated
+        {
+            return Err(Error::Truncated(format!("file entry {}", path)));
+        }
+        //Apply directory attributes last so that read-only directories can still be filled and keep their time
+        for (dest, entry) in directories.iter().rev()
+        {
+            set_directory_mtime(dest, entry.mtime)?;
+            set_permissions(dest, entry.mode)?;
+        }
         return Ok(());
     }
+
+    pub fn unpack(&mut self, target: &Path) -> Result<()>
+    {
+        return self.unpack_filtered(target, &|_| true);
+    }
+
+    //Extracts only the files whose virtual path starts with the given prefix
+    pub fn unpack_prefix(&mut self, target: &Path, prefix: &str) -> Result<()>
+    {
+        return self.unpack_filtered(target, &|path| path.starts_with(prefix));
+    }
+
+    //Extracts only the files whose virtual path matches the given glob pattern (see utils::glob_match)
+    pub fn unpack_glob(&mut self, target: &Path, pattern: &str) -> Result<()>
+    {
+        return self.unpack_filtered(target, &|path| glob_match(pattern, path));
+    }
 }
 
-pub struct Encoder
+pub struct Encoder<TBackend = File>
 {
     pub architecture: Architecture,
     pub platform: Platform,
-    encoder: bpx::Encoder
+    pub store_mtime: bool, //Store modification times of packed entries
+    pub max_mtime: Option<u64>, //Clamp stored modification times for reproducible packages (ex: SOURCE_DATE_EPOCH)
+    pub normalize_permissions: bool, //Store 755 for directories and executables and 644 for other files instead of umask dependent permissions
+    progress: Option<Arc<dyn ProgressObserver>>,
+    links: Vec<(PathBuf, String)>, //Symbolic links packed so far, checked the same way the decoder does
+    encoder: bpx::Encoder<TBackend>
 }
 
-impl Encoder
+impl Encoder<File>
 {
-    pub fn new(file: &Path) -> io::Result<Encoder>
+    pub fn new(file: &Path) -> Result<Encoder>
     {
         let encoder = bpx::Encoder::new(file)?;
 
@@ -245,11 +816,46 @@ impl Encoder
         {
             architecture: Architecture::Any,
             platform: Platform::Any,
+            store_mtime: true,
+            max_mtime: None,
+            normalize_permissions: false,
+            progress: None,
+            links: Vec::new(),
             encoder: encoder
         });
     }
+}
+
+impl<TBackend: io::Write + io::Seek> Encoder<TBackend>
+{
+    pub fn from_writer(writer: TBackend) -> Encoder<TBackend>
+    {
+        return Encoder
+        {
+            architecture: Architecture::Any,
+            platform: Platform::Any,
+            store_mtime: true,
+            max_mtime: None,
+            normalize_permissions: false,
+            progress: None,
+            links: Vec::new(),
+            encoder: bpx::Encoder::from_writer(writer)
+        };
+    }
+
+    //Reports packed entries as well as section and byte events of the underlying BPX (bytes are reported while compressing)
+    pub fn set_progress(&mut self, progress: Arc<dyn ProgressObserver>)
+    {
+        self.encoder.set_progress(progress.clone());
+        self.progress = Some(progress);
+    }
 
-    fn write_file(&mut self, source: &mut dyn Read, data_id: usize) -> io::Result<bool>
+    pub fn into_inner(self) -> TBackend
+    {
+        return self.encoder.into_inner();
+    }
+
+    fn write_file(&mut self, source: &mut dyn Read, data_id: usize) -> Result<bool>
     {
         let data = self.encoder.get_section_by_index(data_id);
         let mut buf: [u8; DATA_WRITE_BUFFER_SIZE] = [0; DATA_WRITE_BUFFER_SIZE];
@@ -267,21 +873,79 @@ impl Encoder
         return Ok(true);
     }
 
-    fn pack_file(&mut self, source: &Path, name: String, data_id1: usize, strings_id: usize) -> io::Result<usize>
+    fn get_stored_permissions(&self, md: &std::fs::Metadata) -> u32
     {
-        let mut data_id = data_id1;
+        let permissions = get_permissions(md);
+        if !self.normalize_permissions
+        {
+            return permissions;
+        }
+        if md.is_dir() || permissions & 0o100 != 0
+        {
+            return 0o755;
+        }
+        return 0o644;
+    }
+
+    fn write_entry_header(&mut self, name: &str, size: u64, mode: u32, md: &std::fs::Metadata, data_id: usize, strings_id: usize) -> Result<()>
+    {
+        let mtime = match (self.store_mtime, self.max_mtime)
+        {
+            (false, _) => 0,
+            (true, Some(max)) => std::cmp::min(get_mtime(md), max),
+            (true, None) => get_mtime(md)
+        };
         let strings = self.encoder.get_section_by_index(strings_id);
-        let size = metadata(source)?.len();
-        let mut fle = File::open(source)?;
-        let mut buf: [u8; 12] = [0; 12];
+        let mut buf: [u8; 24] = [0; 24];
 
-        println!("Writing file {} with {} byte(s)", name, size);
         LittleEndian::write_u64(&mut buf[0..8], size);
-        LittleEndian::write_u32(&mut buf[8..12], write_string(&name, strings)?);
+        LittleEndian::write_u32(&mut buf[8..12], write_string(name, strings)?);
+        LittleEndian::write_u32(&mut buf[12..16], mode);
+        LittleEndian::write_u64(&mut buf[16..24], mtime);
+        let data = self.encoder.get_section_by_index(data_id);
+        data.write_all(&buf)?;
+        return Ok(());
+    }
+
+    fn pack_symlink(&mut self, source: &Path, name: String, data_id: usize, strings_id: usize) -> Result<()>
+    {
+        let md = std::fs::symlink_metadata(source)?;
+        let target = std::fs::read_link(source)?;
+        let target = match target.to_str()
+        {
+            Some(v) => v.replace('\\', "/"),
+            None => panic!("Non unicode paths operating systems cannot run BPXP")
+        };
+
+        //Refuse to produce a package which the decoder would refuse to unpack
+        self.links.push((sanitize_path(&name)?, target.clone()));
+        for (path, target) in &self.links
+        {
+            check_link_target(path, target, &self.links)?;
+        }
+        if let Some(progress) = &self.progress
+        {
+            progress.packing_entry(&name, EntryKind::Symlink, target.len() as u64);
+        }
+        //Link targets are small enough to never be split across data sections
+        self.write_entry_header(&name, target.len() as u64, MODE_SYMLINK, &md, data_id, strings_id)?;
+        let data = self.encoder.get_section_by_index(data_id);
+        data.write_all(target.as_bytes())?;
+        return Ok(());
+    }
+
+    fn pack_file(&mut self, source: &Path, name: String, data_id1: usize, strings_id: usize) -> Result<usize>
+    {
+        let mut data_id = data_id1;
+        let md = metadata(source)?;
+        let size = md.len();
+        let mut fle = File::open(source)?;
+
+        if let Some(progress) = &self.progress
         {
-            let data = self.encoder.get_section_by_index(data_id);
-            data.write(&buf)?;
+            progress.packing_entry(&name, EntryKind::File, size);
         }
+        self.write_entry_header(&name, size, MODE_FILE | self.get_stored_permissions(&md), &md, data_id, strings_id)?;
         while !self.write_file(&mut fle, data_id)?
         {
             data_id = self.encoder.add_section(DATA_SECTION_TYPE, 0)?;
@@ -289,30 +953,47 @@ impl Encoder
         return Ok(data_id);
     }
 
-    fn pack_dir(&mut self, source: &Path, name: String, data_id1: usize, strings_id: usize) -> io::Result<()>
+    fn pack_dir(&mut self, source: &Path, name: String, data_id1: usize, strings_id: usize) -> Result<usize>
     {
         let mut data_id = data_id1;
-        let entries = read_dir(source)?;
-    
-        for rentry in entries
+        let md = metadata(source)?;
+
+        //Directories have their own entry so that empty ones are kept
+        if let Some(progress) = &self.progress
+        {
+            progress.packing_entry(&name, EntryKind::Directory, 0);
+        }
+        self.write_entry_header(&name, 0, MODE_DIRECTORY | self.get_stored_permissions(&md), &md, data_id, strings_id)?;
+        //Sort entries as read_dir order depends on the file system
+        let mut entries = Vec::new();
+        for rentry in read_dir(source)?
+        {
+            entries.push(rentry?);
+        }
+        entries.sort_by_key(|v| v.file_name());
+        for entry in entries
         {
-            let entry = rentry?;
             let mut s = name.clone();
             s.push('/');
             s.push_str(&get_name_from_dir_entry(&entry));
-            if entry.file_type()?.is_dir()
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink()
+            {
+                self.pack_symlink(&entry.path(), s, data_id, strings_id)?;
+            }
+            else if file_type.is_dir()
             {
-                self.pack_dir(&entry.path(), s, data_id, strings_id)?
+                data_id = self.pack_dir(&entry.path(), s, data_id, strings_id)?;
             }
             else
             {
                 data_id = self.pack_file(&entry.path(), s, data_id, strings_id)?;
             }
         }
-        return Ok(());
+        return Ok(data_id);
     }
 
-    pub fn pack_vname(&mut self, source: &Path, vname: &str) -> io::Result<()>
+    pub fn pack_vname(&mut self, source: &Path, vname: &str) -> Result<()>
     {
         let strings = match self.encoder.find_section_by_type(bpx::STRING_SECTION_TYPE)
         {
@@ -328,11 +1009,12 @@ impl Encoder
         }
         else
         {
-            return self.pack_dir(source, String::from(vname), data_section, strings);
+            self.pack_dir(source, String::from(vname), data_section, strings)?;
+            return Ok(());
         }
     }
     
-    pub fn pack(&mut self, source: &Path) -> io::Result<()>
+    pub fn pack(&mut self, source: &Path) -> Result<()>
     {
         let strings = match self.encoder.find_section_by_type(bpx::STRING_SECTION_TYPE)
         {
@@ -348,11 +1030,24 @@ impl Encoder
         }
         else
         {
-            return self.pack_dir(source, get_name_from_path(source)?, data_section, strings);
+            self.pack_dir(source, get_name_from_path(source)?, data_section, strings)?;
+            return Ok(());
         }
     }
 
-    pub fn add_metadata(&mut self, obj: &Object) -> io::Result<()>
+    //Selects how all sections of this package are compressed
+    pub fn set_compression_policy(&mut self, policy: CompressionPolicy) -> Result<()>
+    {
+        return self.encoder.set_compression_policy(policy);
+    }
+
+    //Selects the checksum of all sections of this package (default: weak; use CRC32 for real corruption detection)
+    pub fn set_checksum_method(&mut self, method: ChecksumMethod)
+    {
+        self.encoder.set_checksum_method(method);
+    }
+
+    pub fn add_metadata(&mut self, obj: &Object) -> Result<()>
     {
         let section = self.encoder.add_section(254, 0)?;
         let mut data = self.encoder.get_section_by_index(section);
@@ -361,7 +1056,7 @@ impl Encoder
         return Ok(());
     }
 
-    pub fn save(&mut self) -> io::Result<()>
+    pub fn save(&mut self) -> Result<()>
     {
         match self.architecture
         {
@@ -381,6 +1076,7 @@ impl Encoder
         }
         self.encoder.main_header.type_ext[2] = 0x50;
         self.encoder.main_header.type_ext[3] = 0x4B;
+        self.encoder.main_header.type_ext[4] = ENTRY_FORMAT_VERSION;
         return self.encoder.save();
     }
 }
@@ -0,0 +1,197 @@
+// Copyright (c) 2020, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::io;
+use std::fmt;
+use std::string::FromUtf8Error;
+use super::bpxp::UnsafePathError;
+
+//Every failure of the bpx crate; errors coming back through io::Read/io::Write are unwrapped by From<io::Error>
+#[derive(Debug)]
+pub enum Error
+{
+    Io(io::Error),
+    BadSignature,
+    UnsupportedVersion(u32),
+    UnsupportedType(u8),
+    UnsupportedVariant(u8, u8),
+    UnsupportedEntryFormat(u8),
+    UnknownArchitecture(u8),
+    UnknownPlatform(u8),
+    UnknownEntryKind(u32),
+    ChecksumMismatch { expected: u32, actual: u32 },
+    Truncated(String), //Describes what ended early
+    Utf8(FromUtf8Error),
+    UnknownTypeCode(u8), //Unknown BPXSD value type
+    Inflate(Box<dyn std::error::Error + Send + Sync>),
+    Deflate(Box<dyn std::error::Error + Send + Sync>),
+    Corrupted(String),
+    MissingSection(&'static str),
+    NotFound(String),
+    UnsafePath(UnsafePathError),
+    InvalidCompressionLevel(u32),
+    TooManyValues(usize),
+    InvalidPath(String)
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error
+{
+    //True when the file is damaged (truncated download, flipped bits...) and fetching it again may help
+    pub fn is_corruption(&self) -> bool
+    {
+        return matches!(self,
+            Error::BadSignature
+            | Error::ChecksumMismatch { .. }
+            | Error::Truncated(_)
+            | Error::Utf8(_)
+            | Error::UnknownTypeCode(_)
+            | Error::Inflate(_)
+            | Error::Corrupted(_)
+        );
+    }
+
+    //True when the file is valid but uses a feature this version of bpx does not know about
+    pub fn is_unsupported(&self) -> bool
+    {
+        return matches!(self,
+            Error::UnsupportedVersion(_)
+            | Error::UnsupportedType(_)
+            | Error::UnsupportedVariant(_, _)
+            | Error::UnsupportedEntryFormat(_)
+            | Error::UnknownArchitecture(_)
+            | Error::UnknownPlatform(_)
+            | Error::UnknownEntryKind(_)
+        );
+    }
+
+    fn kind(&self) -> io::ErrorKind
+    {
+        return match self
+        {
+            Error::Io(e) => e.kind(),
+            Error::Truncated(_) => io::ErrorKind::UnexpectedEof,
+            Error::NotFound(_) => io::ErrorKind::NotFound,
+            Error::MissingSection(_)
+            | Error::InvalidCompressionLevel(_)
+            | Error::TooManyValues(_)
+            | Error::InvalidPath(_) => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::InvalidData
+        };
+    }
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        return match self
+        {
+            Error::Io(e) => write!(f, "[BPX] io error: {}", e),
+            Error::BadSignature => write!(f, "[BPX] File is not a BPX: incorrect signature"),
+            Error::UnsupportedVersion(v) => write!(f, "[BPX] Unsupported version of BPX: {}", v),
+            Error::UnsupportedType(t) => write!(f, "[BPX] Unknown type of BPX: {}", *t as char),
+            Error::UnsupportedVariant(a, b) => write!(f, "[BPX] Unsupported BPXP variant {}{}", *a as char, *b as char),
+            Error::UnsupportedEntryFormat(v) => write!(f, "[BPX] Unsupported BPXP entry format version {}", v),
+            Error::UnknownArchitecture(c) => write!(f, "[BPX] Architecture code {} does not exist", c),
+            Error::UnknownPlatform(c) => write!(f, "[BPX] Platform code {} does not exist", c),
+            Error::UnknownEntryKind(k) => write!(f, "[BPX] Unknown packed entry kind {:o}", k),
+            Error::ChecksumMismatch { expected, actual } => write!(f, "[BPX] checksum validation failed {} != {}", actual, expected),
+            Error::Truncated(what) => write!(f, "[BPX] {} is truncated", what),
+            Error::Utf8(e) => write!(f, "[BPX] error loading utf8 string: {}", e),
+            Error::UnknownTypeCode(c) => write!(f, "[BPX] Got unexpected unknown type code ({}) from Structured Data Object", c),
+            Error::Inflate(e) => write!(f, "[BPX] inflate error: {}", e),
+            Error::Deflate(e) => write!(f, "[BPX] deflate error: {}", e),
+            Error::Corrupted(msg) => write!(f, "[BPX] {}", msg),
+            Error::MissingSection(name) => write!(f, "[BPX] could not locate {} section", name),
+            Error::NotFound(path) => write!(f, "[BPX] could not find packed file {}", path),
+            Error::UnsafePath(e) => e.fmt(f),
+            Error::InvalidCompressionLevel(level) => write!(f, "[BPX] invalid compression level {}", level),
+            Error::TooManyValues(count) => write!(f, "[BPX] Structured Data only supports up to 255 maximum values in either array or object, got {} values", count),
+            Error::InvalidPath(path) => write!(f, "[BPX] incorrect path format: {}", path)
+        };
+    }
+}
+
+impl std::error::Error for Error
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        return match self
+        {
+            Error::Io(e) => Some(e),
+            Error::Utf8(e) => Some(e),
+            Error::Inflate(e) => Some(e.as_ref()),
+            Error::Deflate(e) => Some(e.as_ref()),
+            Error::UnsafePath(e) => Some(e),
+            _ => None
+        };
+    }
+}
+
+impl From<io::Error> for Error
+{
+    fn from(err: io::Error) -> Error
+    {
+        //Recover errors raised inside io::Read implementations such as SectionReader
+        if err.get_ref().is_some_and(|v| v.is::<Error>())
+        {
+            return *err.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        return Error::Io(err);
+    }
+}
+
+impl From<Error> for io::Error
+{
+    fn from(err: Error) -> io::Error
+    {
+        return match err
+        {
+            Error::Io(e) => e,
+            _ => io::Error::new(err.kind(), err)
+        };
+    }
+}
+
+impl From<FromUtf8Error> for Error
+{
+    fn from(err: FromUtf8Error) -> Error
+    {
+        return Error::Utf8(err);
+    }
+}
+
+impl From<UnsafePathError> for Error
+{
+    fn from(err: UnsafePathError) -> Error
+    {
+        return Error::UnsafePath(err);
+    }
+}
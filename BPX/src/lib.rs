@@ -27,9 +27,14 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 mod garraylen;
+mod error;
 pub mod bpx;
 pub mod bpxp;
 pub mod section;
 pub mod strings;
 pub mod sd;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod progress;
+
+pub use error::Error;
+pub use error::Result;
@@ -0,0 +1,50 @@
+// Copyright (c) 2020, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use super::section::BPXSectionHeader;
+use super::bpxp::EntryKind;
+
+//Receives progress events from encoders and decoders; every event is ignored by default
+//Implementations must be thread safe as encoders and decoders can be shared across threads
+pub trait ProgressObserver : Send + Sync
+{
+    //Called from the compression threads as soon as a section has been compressed; sections complete in any order
+    fn section_written(&self, _index: usize, _header: &BPXSectionHeader) {}
+
+    //Called once a section has been fully loaded (and decompressed) by a decoder
+    fn section_loaded(&self, _header: &BPXSectionHeader) {}
+
+    //Called when a BPXP encoder starts packing an entry; size is 0 for directories
+    fn packing_entry(&self, _path: &str, _kind: EntryKind, _size: u64) {}
+
+    //Called when a BPXP decoder starts extracting an entry
+    fn unpacking_entry(&self, _path: &str, _kind: EntryKind, _size: u64) {}
+
+    //Called each time count bytes of section data have been compressed by an encoder or bytes of entry data extracted by a BPXP decoder
+    fn bytes_processed(&self, _count: u64) {}
+}
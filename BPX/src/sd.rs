@@ -29,16 +29,16 @@
 // The BPX Structured Data format (BPXSD)
 
 use std::collections::HashMap;
-use std::collections::hash_map::Keys;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Keys;
 use std::vec::Vec;
 use std::string::String;
 use std::ops::Index;
 use std::ops::IndexMut;
 use std::io::Read;
 use std::io::Write;
-use std::io::Result;
-use std::io::Error;
-use std::io::ErrorKind;
+use super::Result;
+use super::Error;
 use byteorder::ByteOrder;
 use byteorder::LittleEndian;
 
@@ -131,7 +131,7 @@ impl IndexMut<usize> for Array
 #[derive(PartialEq, Clone)]
 pub struct Object
 {
-    props: HashMap<u64, Value>,
+    props: BTreeMap<u64, Value>, //Ordered by hash so that serialization is deterministic
     prop_names: Array
 }
 
@@ -141,7 +141,7 @@ impl Object
     {
         return Object
         {
-            props: HashMap::new(),
+            props: BTreeMap::new(),
             prop_names: Array::new()
         }
     }
@@ -210,7 +210,7 @@ fn read_bool(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut flag)? != 1
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Bool(flag[0] == 1));
 }
@@ -221,7 +221,7 @@ fn read_uint8(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 1
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Uint8(val[0]));
 }
@@ -232,7 +232,7 @@ fn read_int8(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 1
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Int8(val[0] as i8));
 }
@@ -243,7 +243,7 @@ fn read_uint16(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 2
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Uint16(LittleEndian::read_u16(&val)));
 }
@@ -254,7 +254,7 @@ fn read_int16(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 2
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Int16(LittleEndian::read_i16(&val)));
 }
@@ -265,7 +265,7 @@ fn read_uint32(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 4
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Uint32(LittleEndian::read_u32(&val)));
 }
@@ -276,7 +276,7 @@ fn read_int32(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 4
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Int32(LittleEndian::read_i32(&val)));
 }
@@ -287,7 +287,7 @@ fn read_uint64(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 8
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Uint64(LittleEndian::read_u64(&val)));
 }
@@ -298,7 +298,7 @@ fn read_int64(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 8
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Int64(LittleEndian::read_i64(&val)));
 }
@@ -309,7 +309,7 @@ fn read_float(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 4
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Float(LittleEndian::read_f32(&val)));
 }
@@ -320,7 +320,7 @@ fn read_double(stream: &mut dyn Read) -> Result<Value>
 
     if stream.read(&mut val)? != 8
     {
-        return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+        return Err(Error::Truncated(String::from("Structured Data Value")));
     }
     return Ok(Value::Double(LittleEndian::read_f64(&val)));
 }
@@ -337,12 +337,12 @@ fn read_string(stream: &mut dyn Read) -> Result<Value>
         let res = stream.read(&mut chr)?;
         if res != 1
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Value"));
+            return Err(Error::Truncated(String::from("Structured Data Value")));
         }
     }
     match String::from_utf8(curs)
     {
-        Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!("[BPX] error loading utf8 string: {}", e))),
+        Err(e) => return Err(Error::Utf8(e)),
         Ok(v) => return Ok(Value::String(v))
     }
 }
@@ -355,7 +355,7 @@ fn parse_object(stream: &mut dyn Read) -> Result<Object>
         let mut buf: [u8; 1] = [0; 1];
         if stream.read(&mut buf)? != 1
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Object"));
+            return Err(Error::Truncated(String::from("Structured Data Object")));
         }
         buf[0]
     };
@@ -365,14 +365,14 @@ fn parse_object(stream: &mut dyn Read) -> Result<Object>
         let mut prop: [u8; 9] = [0; 9];
         if stream.read(&mut prop)? != 9
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Object"));
+            return Err(Error::Truncated(String::from("Structured Data Object")));
         }
         let hash = LittleEndian::read_u64(&prop[0..8]);
         let type_code = prop[8];
         match get_value_parser(type_code)
         {
             Some(func) => obj.raw_set(hash, func(stream)?),
-            None => return Err(Error::new(ErrorKind::InvalidData, format!("[BPX] Got unexpected unknown type code ({}) from Structured Data Object", type_code)))
+            None => return Err(Error::UnknownTypeCode(type_code))
         }
         count -= 1;
     }
@@ -387,7 +387,7 @@ fn parse_array(stream: &mut dyn Read) -> Result<Array>
         let mut buf: [u8; 1] = [0; 1];
         if stream.read(&mut buf)? != 1
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Object"));
+            return Err(Error::Truncated(String::from("Structured Data Object")));
         }
         buf[0]
     };
@@ -397,12 +397,12 @@ fn parse_array(stream: &mut dyn Read) -> Result<Array>
         let mut type_code: [u8; 1] = [0; 1];
         if stream.read(&mut type_code)? != 1
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Unexpected end of input while reading Structured Data Object"));
+            return Err(Error::Truncated(String::from("Structured Data Object")));
         }
         match get_value_parser(type_code[0])
         {
             Some(func) => arr.add(func(stream)?),
-            None => return Err(Error::new(ErrorKind::InvalidData, format!("[BPX] Got unexpected unknown type code ({}) from Structured Data Object", type_code[0])))
+            None => return Err(Error::UnknownTypeCode(type_code[0]))
         }
         count -= 1;
     }
@@ -540,7 +540,7 @@ fn write_object(obj: &Object) -> Result<Vec<u8>>
 
     if count > 255
     {
-        return Err(Error::new(ErrorKind::InvalidInput, format!("[BPX] Structured Data only supports up to 255 maximum values in either array or object, got {} values", count)));
+        return Err(Error::TooManyValues(count));
     }
     v.push(count as u8);
     for hash in obj.get_keys()
@@ -562,7 +562,7 @@ fn write_array(arr: &Array) -> Result<Vec<u8>>
 
     if count > 255
     {
-        return Err(Error::new(ErrorKind::InvalidInput, format!("[BPX] Structured Data only supports up to 255 maximum values in either array or object, got {} values", count)));
+        return Err(Error::TooManyValues(count));
     }
     v.push(count as u8);
     for i in 0..count
@@ -611,11 +611,11 @@ impl DebugSymbols
                             {
                                 symbols.insert(super::utils::hash(&s), s.clone());
                             }
-                            _ => return Err(Error::new(ErrorKind::InvalidData, "[BPX] Wrong value type for debugging symbols"))
+                            _ => return Err(Error::Corrupted(String::from("Wrong value type for debugging symbols")))
                         }
                     }
                 },
-                _ => return Err(Error::new(ErrorKind::InvalidData, "[BPX] Wrong value type for debugging symbols"))
+                _ => return Err(Error::Corrupted(String::from("Wrong value type for debugging symbols")))
             }
         }
         return Ok(DebugSymbols
@@ -35,44 +35,83 @@ use std::io::Write;
 use std::fs::File;
 use std::boxed::Box;
 use xz::stream::Stream;
+use flate2::read::ZlibDecoder;
+use xz::read::XzDecoder;
+use flate2::write::ZlibEncoder;
 use std::num::Wrapping;
+use super::bpx::FLAG_COMPRESS_ZLIB;
+use super::bpx::FLAG_CHECK_CRC32;
+use super::progress::ProgressObserver;
+use super::Error;
+use super::Result;
 
 pub const SIZE_SECTION_HEADER: usize = 24;
+pub const SIZE_SECTION_HEADER_V2: usize = 32;
 
+//Returns the size of a section header for the given BPX version
+pub fn get_section_header_size(version: u32) -> usize
+{
+    if version >= 2
+    {
+        return SIZE_SECTION_HEADER_V2;
+    }
+    return SIZE_SECTION_HEADER;
+}
+
+//Sizes are stored as 32 bits in BPX v1 and 64 bits in BPX v2
 #[derive(Copy, Clone)]
 pub struct BPXSectionHeader
 {
     pub pointer: u64, //+0
-    pub csize: u32, //+8
-    pub size: u32, //+12
-    pub chksum: u32, //+16
-    pub btype: u8, //+20
-    pub flags: u8 //+21
+    pub csize: u64, //+8
+    pub size: u64, //+12 (v1) +16 (v2)
+    pub chksum: u32, //+16 (v1) +24 (v2)
+    pub btype: u8, //+20 (v1) +28 (v2)
+    pub flags: u8 //+21 (v1) +29 (v2)
 }
 
 impl BPXSectionHeader
 {
-    pub fn read<TReader: io::Read>(reader: &mut TReader) -> io::Result<(u32, BPXSectionHeader)>
+    pub fn read<TReader: io::Read>(reader: &mut TReader, version: u32) -> Result<(u32, BPXSectionHeader)>
     {
-        let mut buf: [u8;SIZE_SECTION_HEADER] = [0;SIZE_SECTION_HEADER];
+        let mut buf: [u8; SIZE_SECTION_HEADER_V2] = [0; SIZE_SECTION_HEADER_V2];
+        let size = get_section_header_size(version);
         let mut checksum: u32 = 0;
 
-        reader.read(&mut buf)?;
-        for i in 0..SIZE_SECTION_HEADER
+        if let Err(e) = reader.read_exact(&mut buf[0..size])
+        {
+            if e.kind() == io::ErrorKind::UnexpectedEof
+            {
+                return Err(Error::Truncated(String::from("section header table")));
+            }
+            return Err(Error::from(e));
+        }
+        for i in 0..size
         {
             checksum += buf[i] as u32;
         }
+        if version >= 2
+        {
+            return Ok((checksum, BPXSectionHeader {
+                pointer: LittleEndian::read_u64(&buf[0..8]),
+                csize: LittleEndian::read_u64(&buf[8..16]),
+                size: LittleEndian::read_u64(&buf[16..24]),
+                chksum: LittleEndian::read_u32(&buf[24..28]),
+                btype: buf[28],
+                flags: buf[29]
+            }));
+        }
         return Ok((checksum, BPXSectionHeader {
             pointer: LittleEndian::read_u64(&buf[0..8]),
-            csize: LittleEndian::read_u32(&buf[8..12]),
-            size: LittleEndian::read_u32(&buf[12..16]),
+            csize: LittleEndian::read_u32(&buf[8..12]) as u64,
+            size: LittleEndian::read_u32(&buf[12..16]) as u64,
             chksum: LittleEndian::read_u32(&buf[16..20]),
             btype: buf[20],
             flags: buf[21]
         }));
     }
 
-    pub fn new(size: u32, btype: u8) -> BPXSectionHeader
+    pub fn new(size: u64, btype: u8) -> BPXSectionHeader
     {
         return BPXSectionHeader
         {
@@ -90,39 +129,55 @@ impl BPXSectionHeader
         return self.size > 1000000; //Return true if uncompressed size is greater than 1Mb
     }
 
-    fn to_bytes(&self) -> [u8; SIZE_SECTION_HEADER]
+    //Returns true if this section cannot be represented in a BPX v1 section header
+    pub fn needs_v2(&self) -> bool
     {
-        let mut block: [u8; SIZE_SECTION_HEADER] = [0; SIZE_SECTION_HEADER];
+        return self.size > u32::MAX as u64 || self.csize > u32::MAX as u64;
+    }
+
+    fn to_bytes(&self, version: u32) -> Vec<u8>
+    {
+        let mut block: Vec<u8> = vec![0; get_section_header_size(version)];
         LittleEndian::write_u64(&mut block[0..8], self.pointer);
-        LittleEndian::write_u32(&mut block[8..12], self.csize);
-        LittleEndian::write_u32(&mut block[12..16], self.size);
-        LittleEndian::write_u32(&mut block[16..20], self.chksum);
-        block[20] = self.btype;
-        block[21] = self.flags;
+        if version >= 2
+        {
+            LittleEndian::write_u64(&mut block[8..16], self.csize);
+            LittleEndian::write_u64(&mut block[16..24], self.size);
+            LittleEndian::write_u32(&mut block[24..28], self.chksum);
+            block[28] = self.btype;
+            block[29] = self.flags;
+        }
+        else
+        {
+            LittleEndian::write_u32(&mut block[8..12], self.csize as u32);
+            LittleEndian::write_u32(&mut block[12..16], self.size as u32);
+            LittleEndian::write_u32(&mut block[16..20], self.chksum);
+            block[20] = self.btype;
+            block[21] = self.flags;
+        }
         return block;
     }
 
-    pub fn get_checksum(&self) -> u32
+    pub fn get_checksum(&self, version: u32) -> u32
     {
         let mut checksum: u32 = 0;
-        let buf = self.to_bytes();
-        for i in 0..SIZE_SECTION_HEADER
+        for v in self.to_bytes(version)
         {
-            checksum += buf[i] as u32;
+            checksum += v as u32;
         }
         return checksum;
     }
 
-    pub fn write<TWriter: io::Write>(&self, writer: &mut TWriter) -> io::Result<()>
+    pub fn write<TWriter: io::Write>(&self, writer: &mut TWriter, version: u32) -> Result<()>
     {
-        let buf = self.to_bytes();
-        writer.write(&buf)?;
+        let buf = self.to_bytes(version);
+        writer.write_all(&buf)?;
         writer.flush()?;
         return Ok(());
     }
 }
 
-pub trait Section : io::Read + io::Write + io::Seek
+pub trait Section : io::Read + io::Write + io::Seek + Send
 {
     fn load_in_memory(&mut self) -> io::Result<Vec<u8>>;
     fn size(&self) -> usize; //The computed size of the section
@@ -347,15 +402,140 @@ const FLAG_COMPRESS_XZ: u8 = 0x2;
 const FLAG_CHECK_WEAK: u8 = 0x8;
 const READ_BLOCK_SIZE: usize = 65536;
 
-fn block_based_deflate(input: &mut dyn Read, output: &mut dyn Write, inflated_size: usize) -> io::Result<(usize, u32)>
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CompressionMethod
+{
+    Xz,
+    Zlib
+}
+
+//Controls whether and how a section is compressed when saved
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CompressionPolicy
+{
+    pub method: CompressionMethod,
+    pub level: Option<u32>, //From 0 (fast) to 9 (small); None uses the default level of the codec
+    pub threshold: usize, //Sections smaller than this are stored uncompressed
+    pub store_only: bool //Never compress any section
+}
+
+impl CompressionPolicy
+{
+    pub fn new(method: CompressionMethod) -> CompressionPolicy
+    {
+        return CompressionPolicy
+        {
+            method: method,
+            level: None,
+            threshold: READ_BLOCK_SIZE,
+            store_only: false
+        };
+    }
+
+    pub fn store_only() -> CompressionPolicy
+    {
+        let mut policy = CompressionPolicy::new(CompressionMethod::Xz);
+        policy.store_only = true;
+        return policy;
+    }
+
+    //Called by the encoder when the policy is set so that a bad level is not only found when saving
+    pub fn validate(&self) -> Result<()>
+    {
+        if let Some(level) = self.level
+        {
+            if level > 9
+            {
+                return Err(Error::InvalidCompressionLevel(level));
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ChecksumMethod
+{
+    Weak,
+    Crc32
+}
+
+enum Checksum
+{
+    Weak(Wrapping<u32>),
+    Crc32(crc32fast::Hasher)
+}
+
+impl Checksum
+{
+    fn new(method: ChecksumMethod) -> Checksum
+    {
+        return match method
+        {
+            ChecksumMethod::Weak => Checksum::Weak(Wrapping(0)),
+            ChecksumMethod::Crc32 => Checksum::Crc32(crc32fast::Hasher::new())
+        };
+    }
+
+    fn from_header(header: &BPXSectionHeader) -> Checksum
+    {
+        if header.flags & FLAG_CHECK_CRC32 == FLAG_CHECK_CRC32
+        {
+            return Checksum::new(ChecksumMethod::Crc32);
+        }
+        return Checksum::new(ChecksumMethod::Weak);
+    }
+
+    fn push(&mut self, data: &[u8])
+    {
+        match self
+        {
+            Checksum::Weak(chk) => *chk += read_chksum(data),
+            Checksum::Crc32(hasher) => hasher.update(data)
+        };
+    }
+
+    fn finish(self) -> u32
+    {
+        return match self
+        {
+            Checksum::Weak(chk) => chk.0,
+            Checksum::Crc32(hasher) => hasher.finalize()
+        };
+    }
+
+    fn get_flag(&self) -> u8
+    {
+        return match self
+        {
+            Checksum::Weak(_) => FLAG_CHECK_WEAK,
+            Checksum::Crc32(_) => FLAG_CHECK_CRC32
+        };
+    }
+}
+
+fn check_section_checksum(header: &BPXSectionHeader, chksum: Checksum) -> Result<()>
+{
+    let chksum = chksum.finish();
+    if header.flags & (FLAG_CHECK_WEAK | FLAG_CHECK_CRC32) != 0 && chksum != header.chksum
+    {
+        return Err(Error::ChecksumMismatch
+        {
+            expected: header.chksum,
+            actual: chksum
+        });
+    }
+    return Ok(());
+}
+
+fn block_based_deflate(input: &mut dyn Read, output: &mut dyn Write, inflated_size: usize, level: u32, chksum: &mut Checksum) -> Result<usize>
 {
     let mut count: usize = 0;
-    let mut encoder = match Stream::new_easy_encoder(0, xz::stream::Check::None)
+    let mut encoder = match Stream::new_easy_encoder(level, xz::stream::Check::None)
     {
-        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("[BPX] deflate initialization error: {}", e))),
+        Err(e) => return Err(Error::Deflate(Box::from(e))),
         Ok(v) => v
     };
-    let mut chksum: Wrapping<u32> = Wrapping(0);
     let mut csize: usize = 0;
 
     while count < inflated_size {
@@ -365,7 +545,7 @@ fn block_based_deflate(input: &mut dyn Read, output: &mut dyn Write, inflated_si
         let mut action = xz::stream::Action::Run;
         let mut res = input.read(&mut idata)?;
         count += res;
-        chksum += read_chksum(&idata);
+        chksum.push(&idata[0..res]);
         if count >= inflated_size
         {
             action = xz::stream::Action::Finish;
@@ -377,32 +557,35 @@ fn block_based_deflate(input: &mut dyn Read, output: &mut dyn Write, inflated_si
             match encoder.process_vec(&idata[0..res], &mut odata, action)
             {
                 Ok(s) => status = s,
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] deflate error: {}", e)))
+                Err(e) => return Err(Error::Deflate(Box::from(e)))
             }
             res = 0;
             output.write(&odata)?;
             csize += odata.len();
         }
     }
-    return Ok((csize, chksum.0));
+    return Ok(csize);
 }
 
-fn block_based_inflate(input: &mut dyn Read, output: &mut dyn Write, deflated_size: usize) -> io::Result<u32>
+fn block_based_inflate(input: &mut dyn Read, output: &mut dyn Write, deflated_size: usize, chksum: &mut Checksum) -> Result<()>
 {
     let mut decoder = match Stream::new_stream_decoder(u32::MAX as u64, xz::stream::CONCATENATED)
     {
-        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] inflate error: {}", e))),
+        Err(e) => return Err(Error::Inflate(Box::from(e))),
         Ok(v) => v
     };
     let mut action = xz::stream::Action::Run;
     let mut expected = xz::stream::Status::MemNeeded;
-    let mut chksum: Wrapping<u32> = Wrapping(0);
     let mut remaining = deflated_size;
 
     while remaining > 0 {
         let mut idata: [u8; READ_BLOCK_SIZE] = [0; READ_BLOCK_SIZE];
         let mut status = xz::stream::Status::Ok;
         let mut res = input.read(&mut idata[0..std::cmp::min(READ_BLOCK_SIZE, remaining)])?;
+        if res == 0
+        {
+            return Err(Error::Truncated(String::from("section")));
+        }
         remaining -= res;
         if remaining == 0
         {
@@ -415,87 +598,144 @@ fn block_based_inflate(input: &mut dyn Read, output: &mut dyn Write, deflated_si
             match decoder.process_vec(&idata[0..res], &mut odata, action)
             {
                 Ok(s) => status = s,
-                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] inflate error: {}", e)))
+                Err(e) => return Err(Error::Inflate(Box::from(e)))
             }
             res = 0;
-            chksum += read_chksum(&odata);
+            chksum.push(&odata);
             output.write(&odata)?;
         }
     }
     output.flush()?;
-    return Ok(chksum.0);
+    return Ok(());
+}
+
+fn block_based_deflate_zlib(input: &mut dyn Read, output: &mut dyn Write, inflated_size: usize, level: u32, chksum: &mut Checksum) -> Result<usize>
+{
+    let mut count: usize = 0;
+    let mut encoder = ZlibEncoder::new(output, flate2::Compression::new(level));
+
+    while count < inflated_size {
+        let mut idata: [u8; READ_BLOCK_SIZE] = [0; READ_BLOCK_SIZE];
+        let res = input.read(&mut idata)?;
+        if res == 0
+        {
+            return Err(Error::Truncated(String::from("section")));
+        }
+        count += res;
+        chksum.push(&idata[0..res]);
+        encoder.write_all(&idata[0..res])?;
+    }
+    encoder.try_finish()?;
+    return Ok(encoder.total_out() as usize);
+}
+
+fn block_based_inflate_zlib(input: &mut dyn Read, output: &mut dyn Write, deflated_size: usize, chksum: &mut Checksum) -> Result<()>
+{
+    let mut decoder = ZlibDecoder::new(input.take(deflated_size as u64));
+
+    loop {
+        let mut odata: [u8; READ_BLOCK_SIZE] = [0; READ_BLOCK_SIZE];
+        let res = match decoder.read(&mut odata)
+        {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Inflate(Box::from(e)))
+        };
+        if res == 0
+        {
+            break;
+        }
+        chksum.push(&odata[0..res]);
+        output.write_all(&odata[0..res])?;
+    }
+    output.flush()?;
+    return Ok(());
 }
 
-fn load_section_in_memory(bpx: &mut File, header: &BPXSectionHeader) -> io::Result<InMemorySection>
+fn is_compressed(header: &BPXSectionHeader) -> bool
+{
+    return header.flags & (FLAG_COMPRESS_XZ | FLAG_COMPRESS_ZLIB) != 0;
+}
+
+fn inflate_section(input: &mut dyn Read, output: &mut dyn Write, header: &BPXSectionHeader) -> Result<()>
+{
+    let mut chksum = Checksum::from_header(header);
+    if header.flags & FLAG_COMPRESS_ZLIB == FLAG_COMPRESS_ZLIB
+    {
+        block_based_inflate_zlib(input, output, header.csize as usize, &mut chksum)?;
+    }
+    else
+    {
+        block_based_inflate(input, output, header.csize as usize, &mut chksum)?;
+    }
+    return check_section_checksum(header, chksum);
+}
+
+fn load_section_in_memory<TBackend: io::Read + io::Seek>(bpx: &mut TBackend, header: &BPXSectionHeader) -> Result<InMemorySection>
 {
     bpx.seek(io::SeekFrom::Start(header.pointer))?;
-    if header.flags & FLAG_COMPRESS_XZ == FLAG_COMPRESS_XZ
+    if is_compressed(header)
     {
         let mut section = InMemorySection::new(vec![0; header.size as usize]);
         section.seek(io::SeekFrom::Start(0))?;
-        let chksum = block_based_inflate(bpx, &mut section, header.csize as usize)?;
-        println!("Unpacked section size: {}", section.size());
-        if header.flags & FLAG_CHECK_WEAK == FLAG_CHECK_WEAK && chksum != header.chksum
-        {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] checksum validation failed {} != {}", chksum, header.chksum)));
-        }
+        inflate_section(bpx, &mut section, header)?;
         section.seek(io::SeekFrom::Start(0))?;
         return Ok(section);
     }
     else
     {
         let mut data = vec![0; header.size as usize];
-        bpx.read(&mut data)?;
-        let chksum = read_chksum(&data);
-        if header.flags & FLAG_CHECK_WEAK == FLAG_CHECK_WEAK && chksum.0 != header.chksum
+        if let Err(e) = bpx.read_exact(&mut data)
         {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] checksum validation failed {} != {}", chksum, header.chksum)));
+            if e.kind() == io::ErrorKind::UnexpectedEof
+            {
+                return Err(Error::Truncated(String::from("section")));
+            }
+            return Err(Error::from(e));
         }
+        let mut chksum = Checksum::from_header(header);
+        chksum.push(&data);
+        check_section_checksum(header, chksum)?;
         let mut section = InMemorySection::new(data);
         section.seek(io::SeekFrom::Start(0))?;
         return Ok(section);
     }
 }
 
-fn load_section_as_file(bpx: &mut File, header: &BPXSectionHeader) -> io::Result<FileBasedSection>
+fn load_section_as_file<TBackend: io::Read + io::Seek>(bpx: &mut TBackend, header: &BPXSectionHeader) -> Result<FileBasedSection>
 {
     let mut section = FileBasedSection::new(tempfile::tempfile()?);
 
     bpx.seek(io::SeekFrom::Start(header.pointer))?;
-    if header.flags & FLAG_COMPRESS_XZ == FLAG_COMPRESS_XZ
+    if is_compressed(header)
     {
-        let chksum = block_based_inflate(bpx, &mut section, header.csize as usize)?;
-        println!("Unpacked section size: {}", section.size());
-        if header.flags & FLAG_CHECK_WEAK == FLAG_CHECK_WEAK && chksum != header.chksum
-        {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] checksum validation failed {} != {}", chksum, header.chksum)));
-        }
+        inflate_section(bpx, &mut section, header)?;
     }
     else
     {
         let mut idata: [u8; READ_BLOCK_SIZE] = [0; READ_BLOCK_SIZE];
         let mut count: usize = 0;
-        let mut chksum: Wrapping<u32> = Wrapping(0);
+        let mut chksum = Checksum::from_header(header);
         let mut remaining: usize = header.size as usize;
         while count < header.size as usize
         {
             let res = bpx.read(&mut idata[0..std::cmp::min(READ_BLOCK_SIZE, remaining)])?;
+            if res == 0
+            {
+                return Err(Error::Truncated(String::from("section")));
+            }
             section.write(&idata[0..res])?;
-            chksum += read_chksum(&idata[0..res]);
+            chksum.push(&idata[0..res]);
             count += res;
             remaining -= res;
         }
-        if header.flags & FLAG_CHECK_WEAK == FLAG_CHECK_WEAK && chksum.0 != header.chksum
-        {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("[BPX] checksum validation failed {} != {}", chksum, header.chksum)));
-        }
+        check_section_checksum(header, chksum)?;
         section.flush()?;
     }
     section.seek(io::SeekFrom::Start(0))?;
     return Ok(section);
 }
 
-pub fn open_section(bpx: &mut File, header: &BPXSectionHeader) -> io::Result<Box<dyn Section>>
+pub fn open_section<TBackend: io::Read + io::Seek>(bpx: &mut TBackend, header: &BPXSectionHeader) -> Result<Box<dyn Section>>
 {
     if header.is_huge_section()
     {
@@ -509,7 +749,122 @@ pub fn open_section(bpx: &mut File, header: &BPXSectionHeader) -> io::Result<Box
     }
 }
 
-pub fn create_section(header: &BPXSectionHeader) -> io::Result<Box<dyn Section>>
+enum SectionStream<'a, TBackend: io::Read>
+{
+    Raw(io::Take<&'a mut TBackend>),
+    Xz(XzDecoder<io::Take<&'a mut TBackend>>),
+    Zlib(ZlibDecoder<io::Take<&'a mut TBackend>>)
+}
+
+//Lazily reads (and decompresses) a section straight from the BPX; the checksum is verified once the last byte is read
+pub struct SectionReader<'a, TBackend: io::Read>
+{
+    stream: SectionStream<'a, TBackend>,
+    header: BPXSectionHeader,
+    chksum: Option<Checksum>,
+    remaining: u64
+}
+
+impl<'a, TBackend: io::Read> SectionReader<'a, TBackend>
+{
+    pub fn header(&self) -> &BPXSectionHeader
+    {
+        return &self.header;
+    }
+
+    //Returns the underlying BPX stream in order to read another section
+    pub fn into_inner(self) -> &'a mut TBackend
+    {
+        return match self.stream
+        {
+            SectionStream::Raw(stream) => stream.into_inner(),
+            SectionStream::Xz(stream) => stream.into_inner().into_inner(),
+            SectionStream::Zlib(stream) => stream.into_inner().into_inner()
+        };
+    }
+
+    fn finish(&mut self) -> io::Result<()>
+    {
+        if let Some(chksum) = self.chksum.take()
+        {
+            check_section_checksum(&self.header, chksum)?;
+        }
+        return Ok(());
+    }
+}
+
+impl<'a, TBackend: io::Read> io::Read for SectionReader<'a, TBackend>
+{
+    fn read(&mut self, data: &mut [u8]) -> io::Result<usize>
+    {
+        let len = std::cmp::min(data.len() as u64, self.remaining) as usize;
+        if len == 0
+        {
+            self.finish()?;
+            return Ok(0);
+        }
+        let res = match &mut self.stream
+        {
+            SectionStream::Raw(stream) => stream.read(&mut data[0..len])?,
+            SectionStream::Xz(stream) => match stream.read(&mut data[0..len])
+            {
+                Ok(v) => v,
+                Err(e) => return Err(io::Error::from(Error::Inflate(Box::from(e))))
+            },
+            SectionStream::Zlib(stream) => match stream.read(&mut data[0..len])
+            {
+                Ok(v) => v,
+                Err(e) => return Err(io::Error::from(Error::Inflate(Box::from(e))))
+            }
+        };
+        if res == 0
+        {
+            return Err(io::Error::from(Error::Truncated(String::from("section"))));
+        }
+        if let Some(chksum) = &mut self.chksum
+        {
+            chksum.push(&data[0..res]);
+        }
+        self.remaining -= res as u64;
+        if self.remaining == 0
+        {
+            self.finish()?;
+        }
+        return Ok(res);
+    }
+}
+
+pub fn stream_section<'a, TBackend: io::Read + io::Seek>(bpx: &'a mut TBackend, header: &BPXSectionHeader) -> Result<SectionReader<'a, TBackend>>
+{
+    bpx.seek(io::SeekFrom::Start(header.pointer))?;
+    let stream;
+    if header.flags & FLAG_COMPRESS_ZLIB == FLAG_COMPRESS_ZLIB
+    {
+        stream = SectionStream::Zlib(ZlibDecoder::new(bpx.take(header.csize)));
+    }
+    else if header.flags & FLAG_COMPRESS_XZ == FLAG_COMPRESS_XZ
+    {
+        let decoder = match Stream::new_stream_decoder(u32::MAX as u64, xz::stream::CONCATENATED)
+        {
+            Err(e) => return Err(Error::Inflate(Box::from(e))),
+            Ok(v) => v
+        };
+        stream = SectionStream::Xz(XzDecoder::new_stream(bpx.take(header.csize), decoder));
+    }
+    else
+    {
+        stream = SectionStream::Raw(bpx.take(header.size));
+    }
+    return Ok(SectionReader
+    {
+        stream: stream,
+        header: *header,
+        chksum: Some(Checksum::from_header(header)),
+        remaining: header.size
+    });
+}
+
+pub fn create_section(header: &BPXSectionHeader) -> Result<Box<dyn Section>>
 {
     if header.is_huge_section() || header.size == 0
     {
@@ -525,27 +880,64 @@ pub fn create_section(header: &BPXSectionHeader) -> io::Result<Box<dyn Section>>
     }
 }
 
-pub fn write_section(section: &mut Box<dyn Section>, out: &mut dyn Write) -> io::Result<(usize, u32, u8)>
+//Reports the bytes read from a section as it is being written
+struct ProgressReader<'a, R: Read + ?Sized>
+{
+    inner: &'a mut R,
+    progress: Option<&'a dyn ProgressObserver>
+}
+
+impl<'a, R: Read + ?Sized> Read for ProgressReader<'a, R>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        let res = self.inner.read(buf)?;
+        if let Some(progress) = self.progress
+        {
+            if res > 0
+            {
+                progress.bytes_processed(res as u64);
+            }
+        }
+        return Ok(res);
+    }
+}
+
+pub fn write_section(section: &mut Box<dyn Section>, out: &mut dyn Write, policy: &CompressionPolicy, checksum: ChecksumMethod, progress: Option<&dyn ProgressObserver>) -> Result<(usize, u32, u8)>
 {
-    if section.size() < READ_BLOCK_SIZE
+    let mut chksum = Checksum::new(checksum);
+    let flag = chksum.get_flag();
+    let size = section.size();
+    if policy.store_only || size < policy.threshold
     {
         let mut idata: [u8; READ_BLOCK_SIZE] = [0; READ_BLOCK_SIZE];
         let mut count: usize = 0;
-        let mut chksum: Wrapping<u32> = Wrapping(0);
-        while count < section.size() as usize
+        let mut input = ProgressReader { inner: section, progress: progress };
+        while count < size
         {
-            let res = section.read(&mut idata)?;
+            let res = input.read(&mut idata)?;
             out.write(&idata[0..res])?;
-            chksum += read_chksum(&idata[0..res]);
+            chksum.push(&idata[0..res]);
             count += res;
         }
         section.flush()?;
-        return Ok((section.size(), chksum.0, FLAG_CHECK_WEAK));
+        return Ok((size, chksum.finish(), flag));
     }
     else
     {
-        let size = section.size();
-        let (csize, chksum) = block_based_deflate(section, out, size)?;
-        return Ok((csize, chksum, FLAG_CHECK_WEAK | FLAG_COMPRESS_XZ));
+        let mut input = ProgressReader { inner: section, progress: progress };
+        return match policy.method
+        {
+            CompressionMethod::Xz =>
+            {
+                let csize = block_based_deflate(&mut input, out, size, policy.level.unwrap_or(0), &mut chksum)?;
+                Ok((csize, chksum.finish(), flag | FLAG_COMPRESS_XZ))
+            },
+            CompressionMethod::Zlib =>
+            {
+                let csize = block_based_deflate_zlib(&mut input, out, size, policy.level.unwrap_or(6), &mut chksum)?;
+                Ok((csize, chksum.finish(), flag | FLAG_COMPRESS_ZLIB))
+            }
+        };
     }
 }
@@ -27,10 +27,9 @@
 // SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use std::io::SeekFrom;
-use std::io::Result;
 use std::string::String;
-use std::io::Error;
-use std::io::ErrorKind;
+use super::Result;
+use super::Error;
 use super::section::Section;
 use std::boxed::Box;
 use std::path::Path;
@@ -49,12 +48,12 @@ pub fn get_string(ptr: u32, string_section: &mut Box<dyn Section>) -> Result<Str
         let res = string_section.read(&mut chr)?;
         if res != 1
         {
-            return Err(Error::new(ErrorKind::UnexpectedEof, "[BPX] Reached EOS before null byte, are you sure this BPX is not corrupted/truncated?"));
+            return Err(Error::Truncated(String::from("string section")));
         }
     }
     match String::from_utf8(curs)
     {
-        Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!("[BPX] error loading utf8 string: {}", e))),
+        Err(e) => return Err(Error::Utf8(e)),
         Ok(v) => return Ok(v)
     }
 }
@@ -78,7 +77,7 @@ pub fn get_name_from_path(path: &Path) -> Result<String>
             // The reason BPXP cannot support non-unicode strings in paths is simply because this would be incompatible with unicode systems
             None => panic!("Non unicode paths operating systems cannot run BPXP")
         },
-        None => return Err(Error::new(ErrorKind::InvalidInput, "[BPX] incorrect path format")),
+        None => return Err(Error::InvalidPath(path.display().to_string())),
     }
 }
 
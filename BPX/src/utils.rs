@@ -38,3 +38,55 @@ pub fn hash(s: &str) -> u64
     }
     return val.0;
 }
+
+fn glob_match_chars(pattern: &[char], path: &[char]) -> bool
+{
+    if pattern.is_empty()
+    {
+        return path.is_empty();
+    }
+    if pattern.len() >= 2 && pattern[0] == '*' && pattern[1] == '*'
+    {
+        //"**/" may also match no directory at all
+        if pattern.len() >= 3 && pattern[2] == '/' && glob_match_chars(&pattern[3..], path)
+        {
+            return true;
+        }
+        for i in 0..path.len() + 1
+        {
+            if glob_match_chars(&pattern[2..], &path[i..])
+            {
+                return true;
+            }
+        }
+        return false;
+    }
+    match pattern[0]
+    {
+        '*' =>
+        {
+            for i in 0..path.len() + 1
+            {
+                if glob_match_chars(&pattern[1..], &path[i..])
+                {
+                    return true;
+                }
+                if i < path.len() && path[i] == '/'
+                {
+                    break;
+                }
+            }
+            return false;
+        },
+        '?' => return !path.is_empty() && path[0] != '/' && glob_match_chars(&pattern[1..], &path[1..]),
+        c => return !path.is_empty() && path[0] == c && glob_match_chars(&pattern[1..], &path[1..])
+    }
+}
+
+//Matches a virtual path against a glob pattern; '*' and '?' never match '/' while '**' matches any number of directories
+pub fn glob_match(pattern: &str, path: &str) -> bool
+{
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    return glob_match_chars(&pattern, &path);
+}
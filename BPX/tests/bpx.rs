@@ -1,5 +1,20 @@
+mod common;
+
 use bpx::bpx::Encoder;
 use bpx::bpx::Decoder;
+use bpx::section::ChecksumMethod;
+use bpx::section::CompressionMethod;
+use bpx::section::CompressionPolicy;
+use bpx::section::SIZE_SECTION_HEADER;
+use bpx::section::SIZE_SECTION_HEADER_V2;
+use common::encode;
+use common::roundtrip;
+use common::write_section;
+use common::read_section;
+use common::golden_data;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
 
 #[test]
 fn attempt_write_empty_bpxp()
@@ -11,3 +26,208 @@ fn attempt_write_empty_bpxp()
     assert_eq!(decoder.main_header.version, 1);
     assert_eq!(decoder.main_header.file_size, 40);
 }
+
+#[test]
+fn write_read_in_memory_bpx()
+{
+    let mut decoder = roundtrip(|encoder|
+    {
+        write_section(encoder, 1, b"in memory");
+    });
+    assert_eq!(decoder.main_header.section_num, 1);
+    let header = decoder.get_section_by_index(0);
+    let mut res = Vec::new();
+    decoder.open_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    assert_eq!(res, b"in memory");
+}
+
+#[test]
+fn structured_header_errors()
+{
+    let bytes = encode(|_| {});
+    let mut bad = bytes.clone();
+    bad[0] = b'Z';
+    let err = Decoder::from_reader(Cursor::new(bad)).err().unwrap();
+    assert!(matches!(err, bpx::Error::BadSignature));
+    assert!(err.is_corruption());
+    let mut bad = bytes.clone();
+    bad[20] = 9;
+    let err = Decoder::from_reader(Cursor::new(bad)).err().unwrap();
+    assert!(matches!(err, bpx::Error::UnsupportedVersion(9)));
+    assert!(err.is_unsupported());
+    let err = Decoder::from_reader(Cursor::new(bytes[0..20].to_vec())).err().unwrap();
+    assert!(matches!(err, bpx::Error::Truncated(_)));
+    let mut bad = bytes.clone();
+    bad[30] = 1;
+    let err = Decoder::from_reader(Cursor::new(bad)).err().unwrap();
+    assert!(matches!(err, bpx::Error::ChecksumMismatch { .. }));
+    let err: std::io::Error = err.into();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(matches!(bpx::Error::from(err), bpx::Error::ChecksumMismatch { .. }));
+}
+
+fn many_sections_data(i: usize) -> Vec<u8>
+{
+    return (0..200000 + i * 1000).map(|v| (v % (7 + i)) as u8).collect();
+}
+
+fn encode_many_sections() -> Vec<u8>
+{
+    return encode(|encoder|
+    {
+        for i in 0..8
+        {
+            let section = encoder.add_section(1, 0).unwrap();
+            if i % 2 == 0
+            {
+                encoder.set_section_compression(section, CompressionMethod::Zlib);
+                encoder.set_section_checksum(section, ChecksumMethod::Crc32);
+            }
+            encoder.get_section_by_index(section).write_all(&many_sections_data(i)).unwrap();
+        }
+        write_section(encoder, 2, b"small section");
+    });
+}
+
+#[test]
+fn parallel_compression_matches_sequential()
+{
+    let sequential = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap().install(encode_many_sections);
+    let parallel = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap().install(encode_many_sections);
+    assert_eq!(sequential, parallel);
+    let mut decoder = Decoder::from_reader(Cursor::new(parallel)).unwrap();
+    for i in 0..8
+    {
+        assert_eq!(read_section(&mut decoder, i), many_sections_data(i));
+    }
+}
+
+#[test]
+fn large_sections_are_written_in_order()
+{
+    //The middle section is too big to be buffered and is written straight to the output between the small ones
+    let sizes: [usize; 3] = [100, 64 * 1024 * 1024 + 1, 200];
+    let mut decoder = roundtrip(|encoder|
+    {
+        encoder.set_compression_policy(CompressionPolicy::store_only()).unwrap();
+        for (i, len) in sizes.iter().enumerate()
+        {
+            write_section(encoder, 1, &golden_data(*len, i + 2));
+        }
+    });
+    for (i, len) in sizes.iter().enumerate()
+    {
+        assert!(read_section(&mut decoder, i) == golden_data(*len, i + 2));
+    }
+}
+
+#[test]
+fn output_matches_baseline_encoder()
+{
+    //tests/fixtures/baseline.bpx was written by the original sequential encoder from the same sections
+    let sizes: [(u8, usize, usize); 4] = [(0x1, 100000, 7), (0x2, 200, 3), (0x1, 70000, 13), (0xFF, 4000, 1)];
+    let bytes = encode(|encoder|
+    {
+        for (btype, len, seed) in sizes.iter()
+        {
+            write_section(encoder, *btype, &golden_data(*len, *seed));
+        }
+    });
+    assert!(bytes == include_bytes!("fixtures/baseline.bpx").to_vec());
+}
+
+#[test]
+fn write_bpx_stays_v1()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 13) as u8).collect();
+    let bytes = encode(|encoder|
+    {
+        write_section(encoder, 1, &data);
+        write_section(encoder, 2, b"small");
+    });
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(decoder.main_header.version, 1);
+    assert_eq!(decoder.main_header.file_size, bytes.len() as u64);
+    let first = decoder.get_section_by_index(0);
+    let second = decoder.get_section_by_index(1);
+    assert!(!first.needs_v2() && !second.needs_v2());
+    assert_eq!(first.pointer, (40 + 2 * SIZE_SECTION_HEADER) as u64);
+    assert_eq!(second.pointer, first.pointer + first.csize);
+    assert_eq!(read_section(&mut decoder, 0), data);
+    assert_eq!(read_section(&mut decoder, 1), b"small");
+}
+
+//Rewrites a BPX v1 file using the v2 layout
+fn convert_to_v2(bytes: &[u8]) -> Vec<u8>
+{
+    let decoder = Decoder::from_reader(Cursor::new(bytes.to_vec())).unwrap();
+    let num = decoder.main_header.section_num as usize;
+    let shift = (num * (SIZE_SECTION_HEADER_V2 - SIZE_SECTION_HEADER)) as u64;
+    let mut main = bytes[0..40].to_vec();
+    let mut size = [0; 8];
+    size.copy_from_slice(&main[8..16]);
+    let file_size = u64::from_le_bytes(size) + shift;
+    main[8..16].copy_from_slice(&file_size.to_le_bytes());
+    main[20..24].copy_from_slice(&2u32.to_le_bytes());
+    let mut sht = Vec::new();
+    for i in 0..num
+    {
+        let mut header = decoder.get_section_by_index(i);
+        header.pointer += shift;
+        header.write(&mut sht, 2).unwrap();
+    }
+    let chksum: u32 = main.iter().enumerate().filter(|(i, _)| *i < 4 || *i > 7).map(|(_, v)| *v as u32).sum::<u32>() + sht.iter().map(|v| *v as u32).sum::<u32>();
+    main[4..8].copy_from_slice(&chksum.to_le_bytes());
+    let mut res = main;
+    res.extend_from_slice(&sht);
+    res.extend_from_slice(&bytes[40 + num * SIZE_SECTION_HEADER..]);
+    return res;
+}
+
+#[test]
+fn read_bpx_v2()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 13) as u8).collect();
+    let v1 = encode(|encoder|
+    {
+        write_section(encoder, 1, &data);
+        write_section(encoder, 2, b"small");
+    });
+    assert_eq!(Decoder::from_reader(Cursor::new(v1.clone())).unwrap().main_header.version, 1);
+    let mut decoder = Decoder::from_reader(Cursor::new(convert_to_v2(&v1))).unwrap();
+    assert_eq!(decoder.main_header.version, 2);
+    assert_eq!(read_section(&mut decoder, 0), data);
+    let header = decoder.get_section_by_index(1);
+    let mut res = Vec::new();
+    decoder.open_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    assert_eq!(res, b"small");
+}
+
+#[test]
+fn write_read_bpx_v2()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 13) as u8).collect();
+    let bytes = encode(|encoder|
+    {
+        assert!(encoder.set_minimum_version(3).is_err());
+        encoder.set_minimum_version(2).unwrap();
+        let section = encoder.add_section(1, 0).unwrap();
+        encoder.set_section_checksum(section, ChecksumMethod::Crc32);
+        encoder.get_section_by_index(section).write_all(&data).unwrap();
+        let small = encoder.add_section(2, 5).unwrap();
+        encoder.get_section_by_index(small).write_all(b"small").unwrap();
+    });
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(decoder.main_header.version, 2);
+    assert_eq!(decoder.main_header.file_size, bytes.len() as u64);
+    let header = decoder.get_section_by_index(0);
+    assert_eq!(header.pointer, (40 + 2 * SIZE_SECTION_HEADER_V2) as u64);
+    assert_eq!(header.size, data.len() as u64);
+    assert!(header.csize < header.size);
+    assert_eq!(read_section(&mut decoder, 0), data);
+    let header = decoder.get_section_by_index(1);
+    assert_eq!(header.btype, 2);
+    let mut res = Vec::new();
+    decoder.open_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    assert_eq!(res, b"small");
+}
@@ -0,0 +1,552 @@
+mod common;
+
+use bpx::bpx::Decoder;
+use bpx::bpx::FLAG_CHECK_CRC32;
+use bpx::section::ChecksumMethod;
+use bpx::sd::Object;
+use bpx::sd::Value;
+use bpx::bpxp;
+use common::pack;
+use common::pack_roundtrip;
+use common::read_file;
+use common::make_in_memory_bpxp;
+use common::make_raw_bpxp;
+use common::RecordingObserver;
+use common::RAW_MODE_DIR;
+use common::RAW_MODE_FILE;
+use common::RAW_MODE_SYMLINK;
+use std::io::Cursor;
+use std::io::Read;
+
+#[test]
+fn pack_unpack_in_memory_bpxp()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::fs::write(source.join("a.txt"), b"file a").unwrap();
+    std::fs::write(source.join("sub").join("b.txt"), b"file b").unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+    let target = dir.path().join("target");
+    decoder.unpack(&target).unwrap();
+    assert_eq!(std::fs::read(target.join("source").join("a.txt")).unwrap(), b"file a");
+    assert_eq!(std::fs::read(target.join("source").join("sub").join("b.txt")).unwrap(), b"file b");
+}
+
+#[test]
+fn bpxp_table_of_contents()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::fs::write(source.join("a.txt"), b"file a").unwrap();
+    std::fs::write(source.join("sub").join("b.txt"), b"longer file b").unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+    let mut toc = decoder.table_of_contents().unwrap();
+    assert_eq!(toc.len(), 4);
+    assert_eq!(toc[0].path, "source");
+    assert_eq!(toc[0].kind, bpxp::EntryKind::Directory);
+    assert_eq!(toc[0].offset, 24);
+    assert_eq!(toc[1].offset, toc[0].offset + toc[0].size + 24);
+    assert_eq!(toc[0].sections, toc[1].sections);
+    toc.retain(|v| v.kind == bpxp::EntryKind::File);
+    toc.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(toc[0].path, "source/a.txt");
+    assert_eq!(toc[0].size, 6);
+    assert_eq!(toc[1].path, "source/sub/b.txt");
+    assert_eq!(toc[1].size, 13);
+}
+
+#[test]
+fn bpxp_crc32_checksum()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("a.txt"), b"file a").unwrap();
+    let bytes = pack(|encoder|
+    {
+        encoder.set_checksum_method(ChecksumMethod::Crc32);
+        encoder.pack(&source).unwrap();
+    });
+    let decoder = Decoder::from_reader(Cursor::new(bytes.clone())).unwrap();
+    for i in 0..decoder.main_header.section_num
+    {
+        let header = decoder.get_section_by_index(i as usize);
+        assert_eq!(header.flags & FLAG_CHECK_CRC32, FLAG_CHECK_CRC32);
+    }
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes)).unwrap();
+    assert_eq!(read_file(&mut decoder, "source/a.txt"), b"file a");
+}
+
+#[test]
+fn bpxp_unpack_prefix()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let mut decoder = make_in_memory_bpxp(dir.path());
+    let target = dir.path().join("target");
+    decoder.unpack_prefix(&target, "source/Release/").unwrap();
+    assert_eq!(std::fs::read(target.join("source").join("Release").join("lib.a")).unwrap(), b"release lib");
+    assert!(!target.join("source").join("Debug").exists());
+    assert!(!target.join("source").join("LICENSE.txt").exists());
+}
+
+#[test]
+fn bpxp_unpack_glob()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let mut decoder = make_in_memory_bpxp(dir.path());
+    let target = dir.path().join("target");
+    decoder.unpack_glob(&target, "**/*.a").unwrap();
+    assert_eq!(std::fs::read(target.join("source").join("Release").join("lib.a")).unwrap(), b"release lib");
+    assert_eq!(std::fs::read(target.join("source").join("Debug").join("lib.a")).unwrap(), b"debug lib");
+    assert!(!target.join("source").join("LICENSE.txt").exists());
+}
+
+#[test]
+fn bpxp_open_file()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let mut decoder = make_in_memory_bpxp(dir.path());
+    assert_eq!(read_file(&mut decoder, "source/Debug/lib.a"), b"debug lib");
+    assert_eq!(read_file(&mut decoder, "source/LICENSE.txt"), b"license");
+    assert!(decoder.open_file("source/missing.txt").is_err());
+}
+
+#[test]
+fn sanitize_packed_paths()
+{
+    assert_eq!(bpxp::sanitize_path("a/./b//c.txt").unwrap(), std::path::Path::new("a").join("b").join("c.txt"));
+    for path in ["", "./", "../a", "a/../../b", "/etc/passwd", "\\evil", "C:/Windows", "a\\..\\b", "a\0b"].iter()
+    {
+        let err = bpxp::sanitize_path(path).unwrap_err();
+        assert!(matches!(err, bpx::Error::UnsafePath(_)));
+    }
+}
+
+#[test]
+fn bpxp_unpack_rejects_path_traversal()
+{
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("source")).unwrap();
+    let source = dir.path().join("source").join("evil.txt");
+    std::fs::write(&source, b"evil").unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack_vname(&source, "../evil.txt").unwrap());
+    let target = dir.path().join("target");
+    let err = decoder.unpack(&target).unwrap_err();
+    match err
+    {
+        bpx::Error::UnsafePath(v) => assert_eq!(v.path, "../evil.txt"),
+        _ => panic!("expected an unsafe path error")
+    };
+    assert!(!dir.path().join("evil.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_preserve_permissions_and_symlinks()
+{
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("bin")).unwrap();
+    std::fs::create_dir_all(source.join("lib")).unwrap();
+    std::fs::write(source.join("bin").join("tool"), b"#!/bin/sh").unwrap();
+    std::fs::set_permissions(source.join("bin").join("tool"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(source.join("lib").join("libfoo.so.1.2"), b"library").unwrap();
+    std::os::unix::fs::symlink("libfoo.so.1.2", source.join("lib").join("libfoo.so")).unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+    let toc = decoder.table_of_contents().unwrap();
+    let link = toc.iter().find(|v| v.path == "source/lib/libfoo.so").unwrap();
+    assert_eq!(link.kind, bpxp::EntryKind::Symlink);
+    assert_eq!(link.size, 13);
+    let target = dir.path().join("target");
+    decoder.unpack(&target).unwrap();
+    let tool = std::fs::metadata(target.join("source").join("bin").join("tool")).unwrap();
+    assert_eq!(tool.permissions().mode() & 0o777, 0o755);
+    let lib = target.join("source").join("lib");
+    assert_eq!(std::fs::read_link(lib.join("libfoo.so")).unwrap(), std::path::Path::new("libfoo.so.1.2"));
+    assert_eq!(std::fs::read(lib.join("libfoo.so")).unwrap(), b"library");
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_strip_special_permission_bits()
+{
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("tool");
+    std::fs::write(&source, b"#!/bin/sh").unwrap();
+    std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o4755)).unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+    assert_eq!(decoder.table_of_contents().unwrap()[0].mode, 0o755);
+    let target = dir.path().join("target");
+    decoder.unpack(&target).unwrap();
+    let md = std::fs::metadata(target.join("tool")).unwrap();
+    assert_eq!(md.permissions().mode() & 0o7777, 0o755);
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_normalize_permissions()
+{
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o700)).unwrap();
+    std::fs::write(source.join("tool"), b"#!/bin/sh").unwrap();
+    std::fs::set_permissions(source.join("tool"), std::fs::Permissions::from_mode(0o700)).unwrap();
+    std::fs::write(source.join("data.txt"), b"data").unwrap();
+    std::fs::set_permissions(source.join("data.txt"), std::fs::Permissions::from_mode(0o664)).unwrap();
+    let mut decoder = pack_roundtrip(|encoder|
+    {
+        encoder.normalize_permissions = true;
+        encoder.pack(&source).unwrap();
+    });
+    let toc = decoder.table_of_contents().unwrap();
+    assert_eq!(toc.iter().find(|v| v.path == "source").unwrap().mode, 0o755);
+    assert_eq!(toc.iter().find(|v| v.path == "source/tool").unwrap().mode, 0o755);
+    assert_eq!(toc.iter().find(|v| v.path == "source/data.txt").unwrap().mode, 0o644);
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_unpack_rejects_escaping_symlinks()
+{
+    for target in ["../../outside", "/etc/passwd"].iter()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let bytes = make_raw_bpxp(&[("source", RAW_MODE_DIR, b""), ("source/link", RAW_MODE_SYMLINK, target.as_bytes())], 4096, 0);
+        let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes)).unwrap();
+        let err = decoder.unpack(&dir.path().join("target")).unwrap_err();
+        assert!(matches!(err, bpx::Error::UnsafePath(_)));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_unpack_rejects_chained_escaping_symlinks()
+{
+    //The escaping link is extracted after (x) and before (a) the link it goes through
+    let deeplink: (&str, u32, &[u8]) = ("source/sub/deeplink", RAW_MODE_SYMLINK, b"..");
+    for (name, deeplink_first) in [("source/x", true), ("source/a", false)].iter()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let link: (&str, u32, &[u8]) = (name, RAW_MODE_SYMLINK, b"sub/deeplink/../..");
+        let mut entries = vec![("source", RAW_MODE_DIR, &b""[..]), ("source/sub", RAW_MODE_DIR, &b""[..])];
+        if *deeplink_first
+        {
+            entries.push(deeplink);
+            entries.push(link);
+        }
+        else
+        {
+            entries.push(link);
+            entries.push(deeplink);
+        }
+        let mut decoder = bpxp::Decoder::from_reader(Cursor::new(make_raw_bpxp(&entries, 4096, 0))).unwrap();
+        let target = dir.path().join("target");
+        let err = decoder.unpack(&target).err().unwrap();
+        assert!(matches!(err, bpx::Error::UnsafePath(_)));
+        if let Ok(path) = std::fs::canonicalize(target.join(name))
+        {
+            assert!(path.starts_with(std::fs::canonicalize(&target).unwrap()));
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_pack_rejects_escaping_symlinks()
+{
+    //Same cases as the decoder: absolute, escaping and going up through another link of the package
+    for (link, target) in [("link", "../../outside"), ("link", "/etc/passwd"), ("x", "sub/deeplink/../.."), ("a", "sub/deeplink/../..")].iter()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::os::unix::fs::symlink("..", source.join("sub").join("deeplink")).unwrap();
+        std::os::unix::fs::symlink(target, source.join(link)).unwrap();
+        let mut encoder = bpxp::Encoder::from_writer(Cursor::new(Vec::new()));
+        match encoder.pack(&source)
+        {
+            Err(bpx::Error::UnsafePath(v)) => assert_eq!(&v.path, target),
+            _ => panic!("expected an unsafe path error for {}", target)
+        };
+    }
+    //Links staying inside the package are still accepted
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::os::unix::fs::symlink("../sub/./file", source.join("sub").join("link")).unwrap();
+    let mut encoder = bpxp::Encoder::from_writer(Cursor::new(Vec::new()));
+    assert!(encoder.pack(&source).is_ok());
+}
+
+#[test]
+fn bpxp_table_of_contents_across_sections()
+{
+    let a: Vec<u8> = (0..40).collect();
+    let entries: [(&str, u32, &[u8]); 3] = [("source", RAW_MODE_DIR, b""), ("source/a.bin", RAW_MODE_FILE, &a), ("source/b.txt", RAW_MODE_FILE, b"file b")];
+    //Data sections of 64 bytes: a.bin starts at 48 in the first one and ends at 24 in the second one, followed by b.txt
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(make_raw_bpxp(&entries, 64, 0))).unwrap();
+    let toc = decoder.table_of_contents().unwrap();
+    assert_eq!(toc.len(), 3);
+    assert_eq!(toc[1].path, "source/a.bin");
+    assert_eq!(toc[1].size, 40);
+    assert_eq!(toc[1].sections, vec![1, 2]);
+    assert_eq!(toc[1].offset, 48);
+    assert_eq!(toc[2].path, "source/b.txt");
+    assert_eq!(toc[2].sections, vec![2]);
+    assert_eq!(toc[2].offset, 48);
+    let dir = tempfile::tempdir().unwrap();
+    decoder.unpack(dir.path()).unwrap();
+    assert_eq!(std::fs::read(dir.path().join("source").join("a.bin")).unwrap(), a);
+    assert_eq!(std::fs::read(dir.path().join("source").join("b.txt")).unwrap(), b"file b");
+}
+
+#[test]
+fn bpxp_truncated_entry()
+{
+    let a: Vec<u8> = (0..40).collect();
+    let entries: [(&str, u32, &[u8]); 2] = [("source", RAW_MODE_DIR, b""), ("source/a.bin", RAW_MODE_FILE, &a)];
+    //Data ends in the middle of a.bin, either in its first data section or in the next one
+    for section_size in [4096, 64].iter()
+    {
+        let bytes = make_raw_bpxp(&entries, *section_size, 10);
+        let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes.clone())).unwrap();
+        match decoder.table_of_contents()
+        {
+            Err(bpx::Error::Truncated(v)) => assert!(v.contains("source/a.bin")),
+            _ => panic!("expected a truncated error")
+        };
+        let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes)).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        match decoder.unpack(dir.path())
+        {
+            Err(bpx::Error::Truncated(v)) => assert!(v.contains("source/a.bin")),
+            _ => panic!("expected a truncated error")
+        };
+    }
+}
+
+#[test]
+fn bpxp_open_file_across_sections()
+{
+    let a: Vec<u8> = (0..40).collect();
+    let c: Vec<u8> = (0..150).collect();
+    let entries: [(&str, u32, &[u8]); 4] = [("source", RAW_MODE_DIR, b""), ("source/a.bin", RAW_MODE_FILE, &a), ("source/b.txt", RAW_MODE_FILE, b"file b with data"), ("source/c.bin", RAW_MODE_FILE, &c)];
+    //a.bin crosses the first section boundary, b.txt starts at offset 48 of the second section and c.bin spans the last three
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(make_raw_bpxp(&entries, 64, 0))).unwrap();
+    let toc = decoder.table_of_contents().unwrap();
+    assert_eq!(toc[2].offset, 48);
+    assert_eq!(toc[3].sections, vec![3, 4, 5]);
+    assert_eq!(toc[3].offset, 24);
+    assert_eq!(read_file(&mut decoder, "source/a.bin"), a);
+    assert_eq!(read_file(&mut decoder, "source/b.txt"), b"file b with data");
+    assert_eq!(read_file(&mut decoder, "source/c.bin"), c);
+    //Small reads must give the same result as reading everything at once
+    let mut res = Vec::new();
+    let mut reader = decoder.open_file("source/c.bin").unwrap();
+    let mut buf: [u8; 7] = [0; 7];
+    loop
+    {
+        let len = reader.read(&mut buf).unwrap();
+        if len == 0
+        {
+            break;
+        }
+        res.extend_from_slice(&buf[0..len]);
+    }
+    assert_eq!(res, c);
+}
+
+#[test]
+fn bpxp_open_file_truncated()
+{
+    let a: Vec<u8> = (0..40).collect();
+    let entries: [(&str, u32, &[u8]); 2] = [("source/b.txt", RAW_MODE_FILE, b"file b"), ("source/a.bin", RAW_MODE_FILE, &a)];
+    for section_size in [4096, 64].iter()
+    {
+        let mut decoder = bpxp::Decoder::from_reader(Cursor::new(make_raw_bpxp(&entries, *section_size, 10))).unwrap();
+        match decoder.open_file("source/a.bin")
+        {
+            Err(bpx::Error::Truncated(v)) => assert!(v.contains("source/a.bin")),
+            _ => panic!("expected a truncated error")
+        };
+    }
+    //The package itself is cut short: reading must fail instead of returning partial content
+    let mut bytes = make_raw_bpxp(&entries, 64, 0);
+    bytes.truncate(bytes.len() - 16);
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes)).unwrap();
+    let res = match decoder.open_file("source/a.bin")
+    {
+        Ok(mut reader) => reader.read_to_end(&mut Vec::new()).is_err(),
+        Err(_) => true
+    };
+    assert!(res);
+}
+
+#[cfg(unix)]
+#[test]
+fn bpxp_unpack_rejects_writing_through_symlinks()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("sub")).unwrap();
+    std::os::unix::fs::symlink("sub", source.join("link")).unwrap();
+    let file = dir.path().join("file.txt");
+    std::fs::write(&file, b"data").unwrap();
+    let mut decoder = pack_roundtrip(|encoder|
+    {
+        encoder.pack_vname(&source, "pkg").unwrap();
+        encoder.pack_vname(&file, "pkg/link/file.txt").unwrap();
+    });
+    let err = decoder.unpack(&dir.path().join("target")).unwrap_err();
+    match err
+    {
+        bpx::Error::UnsafePath(v) => assert_eq!(v.path, "pkg/link/file.txt"),
+        _ => panic!("expected an unsafe path error")
+    };
+}
+
+#[test]
+fn bpxp_keep_empty_directories_and_mtime()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(source.join("cache")).unwrap();
+    std::fs::write(source.join("a.txt"), b"file a").unwrap();
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1500000000);
+    std::fs::File::options().write(true).open(source.join("a.txt")).unwrap().set_modified(time).unwrap();
+    let mut decoder = pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+    let toc = decoder.table_of_contents().unwrap();
+    assert_eq!(toc.iter().find(|v| v.path == "source/a.txt").unwrap().mtime, 1500000000);
+    let target = dir.path().join("target");
+    decoder.unpack(&target).unwrap();
+    assert!(target.join("source").join("cache").is_dir());
+    assert_eq!(std::fs::metadata(target.join("source").join("a.txt")).unwrap().modified().unwrap(), time);
+}
+
+#[test]
+fn bpxp_without_mtime()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("a.txt"), b"file a").unwrap();
+    let mut decoder = pack_roundtrip(|encoder|
+    {
+        encoder.store_mtime = false;
+        encoder.pack(&source).unwrap();
+    });
+    assert!(decoder.table_of_contents().unwrap().iter().all(|v| v.mtime == 0));
+}
+
+fn pack_with_metadata(source: &std::path::Path, max_mtime: Option<u64>) -> Vec<u8>
+{
+    let mut obj = Object::new();
+    obj.set("Name", Value::String(String::from("test")));
+    obj.set("Version", Value::String(String::from("1.0.0")));
+    obj.set("Description", Value::String(String::from("deterministic")));
+    obj.add_debug_info();
+    return pack(|encoder|
+    {
+        encoder.max_mtime = max_mtime;
+        encoder.add_metadata(&obj).unwrap();
+        encoder.pack(source).unwrap();
+    });
+}
+
+#[test]
+fn bpxp_output_is_deterministic()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let first = dir.path().join("first").join("source");
+    let second = dir.path().join("second").join("source");
+    //Create the same tree in a different order so that read_dir order may differ
+    for (root, names) in [(&first, ["b.txt", "a.txt", "c", "z.txt", "m.txt"]), (&second, ["m.txt", "c", "z.txt", "a.txt", "b.txt"])].iter()
+    {
+        std::fs::create_dir_all(root).unwrap();
+        for name in names.iter()
+        {
+            match *name
+            {
+                "c" => std::fs::create_dir(root.join(name)).unwrap(),
+                _ => std::fs::write(root.join(name), name.as_bytes()).unwrap()
+            };
+        }
+    }
+    assert_eq!(pack_with_metadata(&first, Some(1000)), pack_with_metadata(&second, Some(1000)));
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(pack_with_metadata(&first, Some(1000)))).unwrap();
+    let toc = decoder.table_of_contents().unwrap();
+    let paths: Vec<&str> = toc.iter().map(|v| v.path.as_str()).collect();
+    assert_eq!(paths, vec!("source", "source/a.txt", "source/b.txt", "source/c", "source/m.txt", "source/z.txt"));
+    assert!(toc.iter().all(|v| v.mtime == 1000));
+}
+
+#[test]
+fn bpxp_unknown_architecture()
+{
+    let mut bytes = pack(|_| {});
+    let mut chksum = [0; 4];
+    chksum.copy_from_slice(&bytes[4..8]);
+    bytes[24] += 5;
+    bytes[4..8].copy_from_slice(&(u32::from_le_bytes(chksum) + 5).to_le_bytes());
+    let err = bpxp::Decoder::from_reader(Cursor::new(bytes)).err().unwrap();
+    assert!(matches!(err, bpx::Error::UnknownArchitecture(9)));
+    assert!(err.is_unsupported());
+    assert!(!err.is_corruption());
+}
+
+#[test]
+fn bpxp_progress_observer()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source");
+    std::fs::create_dir_all(&source).unwrap();
+    std::fs::write(source.join("a.txt"), vec![1; 20000]).unwrap();
+    std::fs::write(source.join("b.txt"), b"hello").unwrap();
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    let bytes = pack(|encoder|
+    {
+        encoder.set_progress(observer.clone());
+        encoder.pack(&source).unwrap();
+    });
+    //Every byte of every section is reported while compressing
+    assert!(observer.bytes.load(std::sync::atomic::Ordering::SeqCst) > 20005);
+    assert_eq!(observer.bytes.load(std::sync::atomic::Ordering::SeqCst), observer.sections.load(std::sync::atomic::Ordering::SeqCst));
+    {
+        let mut events = observer.events.lock().unwrap();
+        assert_eq!(events[0..3], ["pack source Directory 0", "pack source/a.txt File 20000", "pack source/b.txt File 5"]);
+        //Sections are compressed in parallel so they may complete in any order
+        events[3..].sort();
+        assert_eq!(events[3..].len(), 2);
+        assert!(events[3].starts_with("section 0 "));
+        assert!(events[4].starts_with("section 1 "));
+    }
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    let mut decoder = bpxp::Decoder::from_reader(Cursor::new(bytes)).unwrap();
+    decoder.set_progress(observer.clone());
+    decoder.unpack_glob(&dir.path().join("target"), "**/a.txt").unwrap();
+    assert_eq!(observer.bytes.load(std::sync::atomic::Ordering::SeqCst), 20000);
+    assert_eq!(*observer.events.lock().unwrap(), ["load 255", "unpack source/a.txt File 20000"]);
+}
+
+#[test]
+fn bpxp_open_file_reads_table_of_contents_once()
+{
+    let dir = tempfile::tempdir().unwrap();
+    let mut decoder = make_in_memory_bpxp(dir.path());
+    let observer = std::sync::Arc::new(RecordingObserver::default());
+    decoder.set_progress(observer.clone());
+    for path in ["source/LICENSE.txt", "source/Release/lib.a", "source/Debug/lib.a"].iter()
+    {
+        assert!(!read_file(&mut decoder, path).is_empty());
+    }
+    assert!(decoder.open_file("source/missing.txt").is_err());
+    assert_eq!(decoder.table_of_contents().unwrap().len(), 6);
+    //Only the first call loads the string section to build the table of contents
+    assert_eq!(*observer.events.lock().unwrap(), ["load 255"]);
+}
@@ -0,0 +1,152 @@
+//Helpers shared by the integration tests; each test file only uses some of them
+#![allow(dead_code)]
+
+use bpx::bpx::Encoder;
+use bpx::bpx::Decoder;
+use bpx::bpxp;
+use bpx::section::BPXSectionHeader;
+use bpx::strings::write_string;
+use bpx::progress::ProgressObserver;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+
+pub type MemoryEncoder = Encoder<Cursor<Vec<u8>>>;
+pub type MemoryDecoder = Decoder<Cursor<Vec<u8>>>;
+
+//Runs build against an in-memory encoder, saves it and returns the resulting bytes
+pub fn encode<F: FnOnce(&mut MemoryEncoder)>(build: F) -> Vec<u8>
+{
+    let mut encoder = Encoder::from_writer(Cursor::new(Vec::new()));
+    build(&mut encoder);
+    encoder.save().unwrap();
+    return encoder.into_inner().into_inner();
+}
+
+pub fn roundtrip<F: FnOnce(&mut MemoryEncoder)>(build: F) -> MemoryDecoder
+{
+    return Decoder::from_reader(Cursor::new(encode(build))).unwrap();
+}
+
+pub fn write_section(encoder: &mut MemoryEncoder, btype: u8, data: &[u8]) -> usize
+{
+    let section = encoder.add_section(btype, 0).unwrap();
+    encoder.get_section_by_index(section).write_all(data).unwrap();
+    return section;
+}
+
+pub fn read_section(decoder: &mut MemoryDecoder, index: usize) -> Vec<u8>
+{
+    let header = decoder.get_section_by_index(index);
+    let mut res = Vec::new();
+    decoder.stream_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    return res;
+}
+
+pub fn golden_data(len: usize, seed: usize) -> Vec<u8>
+{
+    return (0..len).map(|i| ((i * seed) % 251) as u8).collect();
+}
+
+//Same as encode for BPXP packages
+pub fn pack<F: FnOnce(&mut bpxp::Encoder<Cursor<Vec<u8>>>)>(build: F) -> Vec<u8>
+{
+    let mut encoder = bpxp::Encoder::from_writer(Cursor::new(Vec::new()));
+    build(&mut encoder);
+    encoder.save().unwrap();
+    return encoder.into_inner().into_inner();
+}
+
+pub fn pack_roundtrip<F: FnOnce(&mut bpxp::Encoder<Cursor<Vec<u8>>>)>(build: F) -> bpxp::Decoder<Cursor<Vec<u8>>>
+{
+    return bpxp::Decoder::from_reader(Cursor::new(pack(build))).unwrap();
+}
+
+pub fn read_file<T: std::io::Read + std::io::Seek>(decoder: &mut bpxp::Decoder<T>, path: &str) -> Vec<u8>
+{
+    let mut res = Vec::new();
+    decoder.open_file(path).unwrap().read_to_end(&mut res).unwrap();
+    return res;
+}
+
+pub fn make_in_memory_bpxp(dir: &std::path::Path) -> bpxp::Decoder<Cursor<Vec<u8>>>
+{
+    let source = dir.join("source");
+    std::fs::create_dir_all(source.join("Release")).unwrap();
+    std::fs::create_dir_all(source.join("Debug")).unwrap();
+    std::fs::write(source.join("LICENSE.txt"), b"license").unwrap();
+    std::fs::write(source.join("Release").join("lib.a"), b"release lib").unwrap();
+    std::fs::write(source.join("Debug").join("lib.a"), b"debug lib").unwrap();
+    return pack_roundtrip(|encoder| encoder.pack(&source).unwrap());
+}
+
+pub const RAW_MODE_DIR: u32 = 0o040755;
+pub const RAW_MODE_SYMLINK: u32 = 0o120777;
+pub const RAW_MODE_FILE: u32 = 0o100644;
+
+//Builds a BPXP containing the given raw entries (path, mode, data); used to produce packages the encoder refuses to write
+//The data is split into sections of section_size bytes (entry headers must not be split, as with the encoder) and the last truncate bytes are dropped
+pub fn make_raw_bpxp(entries: &[(&str, u32, &[u8])], section_size: usize, truncate: usize) -> Vec<u8>
+{
+    return encode(|encoder|
+    {
+        encoder.main_header.type_ext[0] = 0x4;
+        encoder.main_header.type_ext[1] = 0x4;
+        encoder.main_header.type_ext[2] = 0x50;
+        encoder.main_header.type_ext[3] = 0x4B;
+        encoder.main_header.type_ext[4] = 0x2;
+        let strings = encoder.add_section(bpx::bpx::STRING_SECTION_TYPE, 0).unwrap();
+        let mut data: Vec<u8> = Vec::new();
+        for (path, mode, content) in entries
+        {
+            let name = write_string(path, encoder.get_section_by_index(strings)).unwrap();
+            data.extend_from_slice(&(content.len() as u64).to_le_bytes());
+            data.extend_from_slice(&name.to_le_bytes());
+            data.extend_from_slice(&mode.to_le_bytes());
+            data.extend_from_slice(&0u64.to_le_bytes());
+            data.extend_from_slice(content);
+        }
+        data.truncate(data.len() - truncate);
+        for chunk in data.chunks(section_size)
+        {
+            write_section(encoder, 0x1, chunk);
+        }
+    });
+}
+
+#[derive(Default)]
+pub struct RecordingObserver
+{
+    pub events: std::sync::Mutex<Vec<String>>,
+    pub bytes: std::sync::atomic::AtomicU64,
+    pub sections: std::sync::atomic::AtomicU64
+}
+
+impl ProgressObserver for RecordingObserver
+{
+    fn section_written(&self, index: usize, header: &BPXSectionHeader)
+    {
+        self.events.lock().unwrap().push(format!("section {} {}", index, header.size));
+        self.sections.fetch_add(header.size, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn section_loaded(&self, header: &BPXSectionHeader)
+    {
+        self.events.lock().unwrap().push(format!("load {}", header.btype));
+    }
+
+    fn packing_entry(&self, path: &str, kind: bpxp::EntryKind, size: u64)
+    {
+        self.events.lock().unwrap().push(format!("pack {} {:?} {}", path, kind, size));
+    }
+
+    fn unpacking_entry(&self, path: &str, kind: bpxp::EntryKind, size: u64)
+    {
+        self.events.lock().unwrap().push(format!("unpack {} {:?} {}", path, kind, size));
+    }
+
+    fn bytes_processed(&self, count: u64)
+    {
+        self.bytes.fetch_add(count, std::sync::atomic::Ordering::SeqCst);
+    }
+}
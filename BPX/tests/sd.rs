@@ -0,0 +1,20 @@
+use bpx::sd::Object;
+use bpx::sd::Value;
+use bpx::sd::write_structured_data;
+
+#[test]
+fn structured_data_is_deterministic()
+{
+    let mut first = Object::new();
+    let mut second = Object::new();
+    for i in 0..50
+    {
+        first.set(&format!("Key{}", i), Value::Uint32(i));
+        second.set(&format!("Key{}", 49 - i), Value::Uint32(49 - i));
+    }
+    let mut a = Vec::new();
+    let mut b = Vec::new();
+    write_structured_data(&mut a, &first).unwrap();
+    write_structured_data(&mut b, &second).unwrap();
+    assert_eq!(a, b);
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use bpx::bpx::Encoder;
+use bpx::bpx::Decoder;
+use bpx::bpx::FLAG_COMPRESS_ZLIB;
+use bpx::bpx::FLAG_CHECK_CRC32;
+use bpx::bpxp;
+use bpx::section::CompressionMethod;
+use bpx::section::ChecksumMethod;
+use bpx::section::CompressionPolicy;
+use bpx::section::BPXSectionHeader;
+use bpx::section::SIZE_SECTION_HEADER;
+use bpx::section::SIZE_SECTION_HEADER_V2;
+use bpx::section::get_section_header_size;
+use common::encode;
+use common::roundtrip;
+use common::write_section;
+use common::read_section;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Write;
+
+#[test]
+fn write_read_zlib_section()
+{
+    let data: Vec<u8> = (0..200000).map(|i| (i % 251) as u8).collect();
+    let mut decoder = roundtrip(|encoder|
+    {
+        let section = encoder.add_section(1, 0).unwrap();
+        encoder.set_section_compression(section, CompressionMethod::Zlib);
+        encoder.get_section_by_index(section).write_all(&data).unwrap();
+    });
+    let header = decoder.get_section_by_index(0);
+    assert_eq!(header.flags & FLAG_COMPRESS_ZLIB, FLAG_COMPRESS_ZLIB);
+    assert!((header.csize as usize) < data.len());
+    let mut res = Vec::new();
+    decoder.open_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    assert_eq!(res, data);
+}
+
+#[test]
+fn write_read_crc32_section()
+{
+    let data: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+    let mut decoder = roundtrip(|encoder|
+    {
+        let section = encoder.add_section(1, 0).unwrap();
+        encoder.set_section_checksum(section, ChecksumMethod::Crc32);
+        encoder.get_section_by_index(section).write_all(&data).unwrap();
+    });
+    let header = decoder.get_section_by_index(0);
+    assert_eq!(header.flags & FLAG_CHECK_CRC32, FLAG_CHECK_CRC32);
+    assert_eq!(header.chksum, crc32fast::hash(&data));
+    let mut res = Vec::new();
+    decoder.open_section(&header).unwrap().read_to_end(&mut res).unwrap();
+    assert_eq!(res, data);
+}
+
+#[test]
+fn detect_reordered_bytes_with_crc32()
+{
+    let mut bytes = encode(|encoder|
+    {
+        let section = encoder.add_section(1, 0).unwrap();
+        encoder.set_section_checksum(section, ChecksumMethod::Crc32);
+        encoder.get_section_by_index(section).write_all(b"0123456789").unwrap();
+    });
+    //Swap two bytes of the section data; a byte sum would not notice
+    let len = bytes.len();
+    bytes.swap(len - 1, len - 2);
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes)).unwrap();
+    let header = decoder.get_section_by_index(0);
+    assert!(decoder.open_section(&header).is_err());
+}
+
+#[test]
+fn stream_compressed_sections()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 13) as u8).collect();
+    let mut decoder = roundtrip(|encoder|
+    {
+        write_section(encoder, 1, &data);
+        let zlib = encoder.add_section(1, 0).unwrap();
+        encoder.set_section_compression(zlib, CompressionMethod::Zlib);
+        encoder.set_section_checksum(zlib, ChecksumMethod::Crc32);
+        encoder.get_section_by_index(zlib).write_all(&data).unwrap();
+    });
+    for i in 0..2
+    {
+        assert_eq!(read_section(&mut decoder, i), data);
+    }
+}
+
+#[test]
+fn stream_section_detects_corruption()
+{
+    let mut bytes = encode(|encoder|
+    {
+        write_section(encoder, 1, b"0123456789");
+    });
+    let len = bytes.len();
+    bytes[len - 1] = b'X';
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes)).unwrap();
+    let header = decoder.get_section_by_index(0);
+    let mut reader = decoder.stream_section(&header).unwrap();
+    let mut res = Vec::new();
+    assert!(reader.read_to_end(&mut res).is_err());
+}
+
+#[test]
+fn structured_section_errors()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 13) as u8).collect();
+    let mut bytes = encode(|encoder|
+    {
+        write_section(encoder, 1, &data);
+    });
+    let len = bytes.len();
+    bytes[len - 40] ^= 0xFF;
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes.clone())).unwrap();
+    let header = decoder.get_section_by_index(0);
+    assert!(decoder.open_section(&header).err().unwrap().is_corruption());
+    //Errors raised while streaming go through io::Read but can be recovered
+    let mut res = Vec::new();
+    let err = decoder.stream_section(&header).unwrap().read_to_end(&mut res).unwrap_err();
+    assert!(bpx::Error::from(err).is_corruption());
+    let mut decoder = Decoder::from_reader(Cursor::new(bytes[0..len - 100].to_vec())).unwrap();
+    let err = decoder.open_section(&header).err().unwrap();
+    assert!(matches!(err, bpx::Error::Truncated(_)));
+}
+
+fn roundtrip_with_policy(policy: CompressionPolicy, data: &[u8]) -> (BPXSectionHeader, Vec<u8>)
+{
+    let mut decoder = roundtrip(|encoder|
+    {
+        encoder.set_compression_policy(policy).unwrap();
+        write_section(encoder, 1, data);
+    });
+    return (decoder.get_section_by_index(0), read_section(&mut decoder, 0));
+}
+
+#[test]
+fn compression_policy()
+{
+    let data: Vec<u8> = (0..300000).map(|i| (i % 251) as u8).collect();
+    let (header, res) = roundtrip_with_policy(CompressionPolicy::store_only(), &data);
+    assert_eq!(header.flags & (FLAG_COMPRESS_ZLIB | 0x2), 0);
+    assert_eq!(header.csize, header.size);
+    assert_eq!(res, data);
+    let mut policy = CompressionPolicy::new(CompressionMethod::Zlib);
+    policy.threshold = data.len() + 1;
+    let (header, _) = roundtrip_with_policy(policy, &data);
+    assert_eq!(header.flags & FLAG_COMPRESS_ZLIB, 0);
+    policy.threshold = 0;
+    for level in [1, 9].iter()
+    {
+        policy.level = Some(*level);
+        let (header, res) = roundtrip_with_policy(policy, &data);
+        assert_ne!(header.flags & FLAG_COMPRESS_ZLIB, 0);
+        assert_eq!(res, data);
+    }
+    //Invalid levels are refused as soon as the policy is set
+    policy.level = Some(10);
+    assert!(matches!(policy.validate(), Err(bpx::Error::InvalidCompressionLevel(10))));
+    let mut encoder = Encoder::from_writer(Cursor::new(Vec::new()));
+    let section = encoder.add_section(1, 0).unwrap();
+    assert!(matches!(encoder.set_compression_policy(policy), Err(bpx::Error::InvalidCompressionLevel(10))));
+    assert!(matches!(encoder.set_section_policy(section, policy), Err(bpx::Error::InvalidCompressionLevel(10))));
+    let mut encoder = bpxp::Encoder::from_writer(Cursor::new(Vec::new()));
+    assert!(encoder.set_compression_policy(policy).is_err());
+}
+
+#[test]
+fn section_header_v2_round_trip()
+{
+    let mut header = BPXSectionHeader::new(u32::MAX as u64 + 10, 1);
+    header.csize = u32::MAX as u64 + 5;
+    header.pointer = 72;
+    assert!(header.needs_v2());
+    let mut buf = Vec::new();
+    header.write(&mut buf, 2).unwrap();
+    assert_eq!(buf.len(), SIZE_SECTION_HEADER_V2);
+    let (_, res) = BPXSectionHeader::read(&mut Cursor::new(buf), 2).unwrap();
+    assert_eq!(res.size, header.size);
+    assert_eq!(res.csize, header.csize);
+    assert_eq!(res.pointer, 72);
+    assert_eq!(res.btype, 1);
+}
+
+fn make_section_header() -> BPXSectionHeader
+{
+    let mut header = BPXSectionHeader::new(0x01020304, 0x7);
+    header.pointer = 0x1122334455667788;
+    header.csize = 0x0A0B0C0D;
+    header.chksum = 0xDEADBEEF;
+    header.flags = 0x9;
+    return header;
+}
+
+fn write_section_header(header: &BPXSectionHeader, version: u32) -> Vec<u8>
+{
+    let mut buf = Vec::new();
+    header.write(&mut buf, version).unwrap();
+    assert_eq!(buf.len(), get_section_header_size(version));
+    //The table checksum is the sum of the bytes actually written
+    assert_eq!(header.get_checksum(version), buf.iter().map(|v| *v as u32).sum::<u32>());
+    let (chksum, res) = BPXSectionHeader::read(&mut Cursor::new(buf.clone()), version).unwrap();
+    assert_eq!(chksum, header.get_checksum(version));
+    assert_eq!(res.pointer, header.pointer);
+    assert_eq!(res.csize, header.csize);
+    assert_eq!(res.size, header.size);
+    assert_eq!(res.chksum, header.chksum);
+    assert_eq!(res.btype, header.btype);
+    assert_eq!(res.flags, header.flags);
+    return buf;
+}
+
+#[test]
+fn section_header_v1_layout()
+{
+    let buf = write_section_header(&make_section_header(), 1);
+    assert_eq!(buf.len(), SIZE_SECTION_HEADER);
+    assert_eq!(buf[0..8], 0x1122334455667788u64.to_le_bytes());
+    assert_eq!(buf[8..12], 0x0A0B0C0Du32.to_le_bytes());
+    assert_eq!(buf[12..16], 0x01020304u32.to_le_bytes());
+    assert_eq!(buf[16..20], 0xDEADBEEFu32.to_le_bytes());
+    assert_eq!(buf[20], 0x7);
+    assert_eq!(buf[21], 0x9);
+    assert_eq!(buf[22..24], [0, 0]);
+}
+
+#[test]
+fn section_header_v2_layout()
+{
+    let buf = write_section_header(&make_section_header(), 2);
+    assert_eq!(buf.len(), SIZE_SECTION_HEADER_V2);
+    assert_eq!(buf[0..8], 0x1122334455667788u64.to_le_bytes());
+    assert_eq!(buf[8..16], 0x0A0B0C0Du64.to_le_bytes());
+    assert_eq!(buf[16..24], 0x01020304u64.to_le_bytes());
+    assert_eq!(buf[24..28], 0xDEADBEEFu32.to_le_bytes());
+    assert_eq!(buf[28], 0x7);
+    assert_eq!(buf[29], 0x9);
+    assert_eq!(buf[30..32], [0, 0]);
+}
+
+#[test]
+fn section_header_needs_v2()
+{
+    let mut header = BPXSectionHeader::new(u32::MAX as u64, 1);
+    header.csize = u32::MAX as u64;
+    assert!(!header.needs_v2());
+    header.size = u32::MAX as u64 + 1;
+    assert!(header.needs_v2());
+    //A section growing when compressed must switch on its compressed size alone
+    header.size = u32::MAX as u64;
+    header.csize = u32::MAX as u64 + 1;
+    assert!(header.needs_v2());
+    assert!(!make_section_header().needs_v2());
+}
@@ -0,0 +1,13 @@
+use bpx::utils::glob_match;
+
+#[test]
+fn glob_match_paths()
+{
+    assert!(glob_match("source/*.txt", "source/LICENSE.txt"));
+    assert!(!glob_match("source/*.txt", "source/sub/LICENSE.txt"));
+    assert!(glob_match("source/**/*.a", "source/Release/lib.a"));
+    assert!(glob_match("source/**/*.a", "source/lib.a"));
+    assert!(glob_match("**", "source/Release/lib.a"));
+    assert!(glob_match("source/?ebug/lib.a", "source/Debug/lib.a"));
+    assert!(!glob_match("source/?", "source/ab"));
+}
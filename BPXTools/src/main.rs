@@ -33,8 +33,10 @@ use std::path::Path;
 mod bpxinfo;
 mod pack;
 mod unpack;
+mod list;
 mod type_ext_maps;
 mod printsd;
+mod progress;
 
 fn error(err: &std::io::Error)
 {
@@ -60,10 +62,17 @@ fn main() {
         )
         (@subcommand pack =>
             (about: "Create a BPX type P (Package) with given data inside")
+            (@arg verbose: -v --verbose "Prints packed files and sections")
             (@arg files: +required ... "List of files to pack")
         )
         (@subcommand unpack =>
             (about: "Unpacks a given BPX type P (Package) file")
+            (@arg prefix: -p --prefix +takes_value conflicts_with[glob] "Only unpack files whose path starts with the given prefix")
+            (@arg glob: -g --glob +takes_value "Only unpack files whose path matches the given glob pattern")
+            (@arg verbose: -v --verbose "Prints unpacked files and sections")
+        )
+        (@subcommand list =>
+            (about: "Lists the files contained in a given BPX type P (Package) file")
         )
     ).setting(AppSettings::SubcommandRequiredElseHelp).get_matches();
     let file = matches.value_of("file").unwrap();
@@ -84,9 +93,17 @@ fn main() {
             Err(e) => error(&e)
         }
     }
-    if matches.subcommand_matches("unpack").is_some()
+    if let Some(matches) = matches.subcommand_matches("unpack")
+    {
+        match unpack::run(Path::new(file), matches)
+        {
+            Ok(()) => std::process::exit(0),
+            Err(e) => error(&e)
+        }
+    }
+    if matches.subcommand_matches("list").is_some()
     {
-        match unpack::run(Path::new(file))
+        match list::run(Path::new(file))
         {
             Ok(()) => std::process::exit(0),
             Err(e) => error(&e)
@@ -29,11 +29,17 @@
 use std::path::Path;
 use clap::ArgMatches;
 use std::io::Result;
+use std::sync::Arc;
 use bpx::bpxp;
+use crate::progress::ConsoleProgress;
 
 pub fn run(file: &Path, matches: &ArgMatches) -> Result<()>
 {
     let mut encoder = bpxp::Encoder::new(file)?;
+    if matches.is_present("verbose")
+    {
+        encoder.set_progress(Arc::new(ConsoleProgress {}));
+    }
     let files: Vec<&str> = matches.values_of("files").unwrap().collect();
 
     for v in files
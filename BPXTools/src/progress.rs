@@ -0,0 +1,62 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use bpx::progress::ProgressObserver;
+use bpx::section::BPXSectionHeader;
+use bpx::bpxp::EntryKind;
+
+//Prints packing and unpacking events to standard output (--verbose)
+pub struct ConsoleProgress {}
+
+impl ProgressObserver for ConsoleProgress
+{
+    fn section_written(&self, index: usize, header: &BPXSectionHeader)
+    {
+        println!("Writing section #{}: Size = {}, Size after compression = {}", index, header.size, header.csize);
+    }
+
+    fn section_loaded(&self, header: &BPXSectionHeader)
+    {
+        println!("Loaded section: Size = {}, Size before decompression = {}", header.size, header.csize);
+    }
+
+    fn packing_entry(&self, path: &str, kind: EntryKind, size: u64)
+    {
+        match kind
+        {
+            EntryKind::File => println!("Writing file {} with {} byte(s)", path, size),
+            EntryKind::Symlink => println!("Writing symbolic link {}", path),
+            EntryKind::Directory => println!("Writing directory {}", path)
+        };
+    }
+
+    fn unpacking_entry(&self, path: &str, _: EntryKind, size: u64)
+    {
+        println!("Reading {} with {} byte(s)...", path, size);
+    }
+}
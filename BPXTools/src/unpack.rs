@@ -29,10 +29,28 @@
 use bpx::bpxp;
 use std::path::Path;
 use std::io::Result;
+use clap::ArgMatches;
+use std::sync::Arc;
+use crate::progress::ConsoleProgress;
 
-pub fn run(file: &Path) -> Result<()>
+pub fn run(file: &Path, matches: &ArgMatches) -> Result<()>
 {
     let mut decoder = bpxp::Decoder::new(file)?;
-    decoder.unpack(Path::new("."))?;
+    if matches.is_present("verbose")
+    {
+        decoder.set_progress(Arc::new(ConsoleProgress {}));
+    }
+    if let Some(prefix) = matches.value_of("prefix")
+    {
+        decoder.unpack_prefix(Path::new("."), prefix)?;
+    }
+    else if let Some(pattern) = matches.value_of("glob")
+    {
+        decoder.unpack_glob(Path::new("."), pattern)?;
+    }
+    else
+    {
+        decoder.unpack(Path::new("."))?;
+    }
     return Ok(());
 }
\ No newline at end of file
@@ -7,6 +7,8 @@ $Test = {
 
 sub TestBegin {
     CRLFToLF("../LICENSE.txt", "test/LICENSE.txt");
+    chmod(0644, "test/LICENSE.txt");
+    utime(1609459200, 1609459200, "test/LICENSE.txt");
 }
 
 sub TestEnd {
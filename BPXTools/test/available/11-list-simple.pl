@@ -0,0 +1,14 @@
+$Test = {
+    Name => "List (SIMPLE)",
+    Command => "-f test/available/test.bpx list",
+    Description => "Test the list command",
+    Status => 0
+};
+
+sub TestBegin {
+
+}
+
+sub TestEnd {
+    return 1;
+}
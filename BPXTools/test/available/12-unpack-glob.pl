@@ -0,0 +1,17 @@
+$Test = {
+    Name => "Unpack (GLOB)",
+    Command => "-f test/available/test.bpx unpack -g \"*.txt\"",
+    Description => "Test the unpack command with a glob filter",
+    Status => 0
+};
+
+sub TestBegin {
+    CRLFToLF("../LICENSE.txt", "test/LICENSE.txt");
+}
+
+sub TestEnd {
+    my $res = EnsureEqual("LICENSE.txt", "test/LICENSE.txt");
+    unlink("LICENSE.txt");
+    unlink("test/LICENSE.txt");
+    return $res;
+}
@@ -0,0 +1,17 @@
+$Test = {
+    Name => "Unpack (PREFIX)",
+    Command => "-f test/available/test.bpx unpack -p Release/",
+    Description => "Test the unpack command with a prefix filter that matches nothing",
+    Status => 0
+};
+
+sub TestBegin {
+
+}
+
+sub TestEnd {
+    my $res = !(-e "LICENSE.txt");
+    unlink("LICENSE.txt");
+    unlink("test/LICENSE.txt");
+    return $res;
+}
@@ -0,0 +1,17 @@
+$Test = {
+    Name => "Unpack (LEGACY)",
+    Command => "-f test/available/legacy.bpx unpack",
+    Description => "Test the unpack command with a package using the original entry format",
+    Status => 0
+};
+
+sub TestBegin {
+    CRLFToLF("../LICENSE.txt", "test/LICENSE.txt");
+}
+
+sub TestEnd {
+    my $res = EnsureEqual("LICENSE.txt", "test/LICENSE.txt");
+    unlink("LICENSE.txt");
+    unlink("test/LICENSE.txt");
+    return $res;
+}
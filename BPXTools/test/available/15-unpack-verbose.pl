@@ -0,0 +1,17 @@
+$Test = {
+    Name => "Unpack (VERBOSE)",
+    Command => "-f test/available/test.bpx unpack -v",
+    Description => "Test the unpack command printing unpacked files and sections",
+    Status => 0
+};
+
+sub TestBegin {
+    CRLFToLF("../LICENSE.txt", "test/LICENSE.txt");
+}
+
+sub TestEnd {
+    my $res = EnsureEqual("LICENSE.txt", "test/LICENSE.txt");
+    unlink("LICENSE.txt");
+    unlink("test/LICENSE.txt");
+    return $res;
+}
@@ -44,13 +44,15 @@ pub enum ErrorDomain
     Settings,
     Registry,
     Generator,
-    Toolchain
+    Toolchain,
+    Lockfile
 }
 
 pub enum Error
 {
     Io(ErrorDomain, io::Error),
     Lua(ErrorDomain, rlua::Error),
+    Bpx(ErrorDomain, bpx::Error),
     Generic(ErrorDomain, String)
 }
 
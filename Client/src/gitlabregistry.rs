@@ -76,6 +76,28 @@ impl GitLabRegistry
         }
     }
 
+    //Returns the entries of all the pages of a search
+    fn search_all(&mut self, pattern: &str) -> Result<Vec<glgp::types::PackageEntry>>
+    {
+        let mut res = Vec::new();
+        let mut page = 1;
+        loop
+        {
+            let data = match self.list.search(page, pattern)
+            {
+                Ok(v) => v,
+                Err(e) => return Err(Error::Generic(ErrorDomain::Registry, format!("A HTTP request has failed: {}", e)))
+            };
+            if data.is_empty()
+            {
+                break;
+            }
+            res.extend(data);
+            page += 1;
+        }
+        return Ok(res);
+    }
+
     fn list_file_names(&mut self, package: &glgp::types::PackageEntry) -> Result<Vec<String>>
     {
         let mut res = Vec::new();
@@ -222,6 +244,37 @@ impl PackageRegistry for GitLabRegistry
         return Ok(None);
     }
 
+    fn list_versions(&mut self, name: &str) -> Result<Vec<String>>
+    {
+        //GitLab search also returns packages whose name only contains the searched name
+        return Ok(self.search_all(name)?.into_iter().filter(|v| v.name == name).map(|v| v.version).collect());
+    }
+
+    fn list_packages(&mut self, name: &str) -> Result<Vec<Package>>
+    {
+        let mut res = Vec::new();
+        //Page through the search only once; calling find for each version would page through it again every time
+        for entry in self.search_all(name)?
+        {
+            if entry.name == name
+            {
+                let files = self.list_file_names(&entry)?;
+                res.push(Package
+                {
+                    name: entry.name,
+                    version: entry.version,
+                    files: files
+                });
+            }
+        }
+        return Ok(res);
+    }
+
+    fn search(&mut self, pattern: &str) -> Result<Vec<Package>>
+    {
+        return Ok(self.search_all(pattern)?.iter().map(|v| Package::new(&v.name, &v.version)).collect());
+    }
+
     fn download(&mut self, target_folder: &Path, package: &Package, file_name: &str) -> Result<()>
     {
         if let Some(mgr) = &mut self.manager
@@ -30,6 +30,7 @@ use std::string::String;
 use std::path::Path;
 use std::fs;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use bpx::bpxp;
 use bpx::sd;
 use std::io;
@@ -49,12 +50,29 @@ use crate::luaengine::Dependency;
 use crate::settings::Settings;
 use crate::settings::RegistryInfo;
 use crate::registry::open_package_registry;
+use crate::registry::PackageRegistry;
+use crate::registry::Package;
+use crate::registry::is_valid_name;
+use crate::version::VersionReq;
+use crate::lockfile::Lockfile;
+use crate::lockfile::LockedPackage;
+use crate::lockfile::hash_file;
 use crate::common::read_property_map;
 
-fn check_file_name_match(profile: &Profile, file_name: &str) -> bool
+//Dependencies declared by the install function of the project, recorded for each toolchain by fpkg install
+const DECLARED_DEPENDENCIES_FILE: &str = "declared-dependencies.json";
+
+//Number of times a package is downloaded before giving up when it is corrupted or does not match fpkg.lock
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 2;
+
+pub fn check_file_name_match(profile: &Profile, file_name: &str) -> bool
 {
     //File format: build-Platform-Arch-CompilerName-CompilerVersion.bpx
-    let components = &file_name[0..file_name.len() - 4].split('-').collect::<Vec<&str>>();
+    let components = match file_name.strip_suffix(".bpx")
+    {
+        Some(v) => v.split('-').collect::<Vec<&str>>(),
+        None => return false
+    };
     let platform = &profile.platform;
     let arch = &profile.architecture;
     let compiler = &profile.compiler_name;
@@ -91,6 +109,41 @@ fn check_file_name_match(profile: &Profile, file_name: &str) -> bool
     return false;
 }
 
+//Dependency names are joined onto the toolchain path so a package must not be able to point them anywhere else
+fn ensure_valid_dependency_name(package: &str, name: &str) -> Result<()>
+{
+    if !is_valid_name(name)
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, format!("Refusing package {}: invalid dependency name '{}'", package, name)));
+    }
+    return Ok(());
+}
+
+fn build_dependency_info(package: &str, deps: &sd::Array) -> Result<json::JsonValue>
+{
+    let mut arr = json::JsonValue::new_array();
+    for i in 0..deps.len()
+    {
+        if let sd::Value::Object(dep) = &deps[i]
+        {
+            if let (Some(sd::Value::String(name)), Some(sd::Value::String(version))) = (dep.get("Name"), dep.get("Version"))
+            {
+                ensure_valid_dependency_name(package, name)?;
+                let mut j = json::JsonValue::new_object();
+                j["Name"] = json::JsonValue::String(name.clone());
+                j["Version"] = json::JsonValue::String(version.clone());
+                if let Err(e) = arr.push(j)
+                {
+                    return Err(Error::Generic(ErrorDomain::Installer, format!("Error building json: {}", e)));
+                }
+                continue;
+            }
+        }
+        return Err(Error::Generic(ErrorDomain::Installer, String::from("Incorrect type for key 'Dependencies'")));
+    }
+    return Ok(arr);
+}
+
 fn build_package_info(obj: &sd::Object) -> Result<json::object::Object>
 {
     let profile = Profile::from_bpxsd(&obj)?;
@@ -121,6 +174,17 @@ fn build_package_info(obj: &sd::Object) -> Result<json::object::Object>
             sd::Value::String(s) => j.insert("Description", json::JsonValue::String(s.clone())),
             _ => return Err(Error::Generic(ErrorDomain::Installer, format!("Incorrect type for key 'Description'")))
         };
+        //Packages built by older versions of fpkg do not record their dependencies
+        match obj.get("Dependencies")
+        {
+            Some(sd::Value::Array(deps)) =>
+            {
+                let deps = build_dependency_info(&j["Name"].to_string(), deps)?;
+                j.insert("Dependencies", deps);
+            },
+            Some(_) => return Err(Error::Generic(ErrorDomain::Installer, String::from("Incorrect type for key 'Dependencies'"))),
+            None => j.insert("Dependencies", json::JsonValue::new_array())
+        };
         return Ok(j);
     }
     return Err(Error::Generic(ErrorDomain::Installer, String::from("BPX Error: missing package header metadata (name, type, version and description)")));
@@ -131,12 +195,12 @@ fn unpack_bpx(file: &Path, folder: &Path) -> Result<()>
     let mut decoder = match bpxp::Decoder::new(&file)
     {
         Ok(v) => v,
-        Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+        Err(e) => return Err(Error::Bpx(ErrorDomain::Installer, e))
     };
     let obj = match decoder.open_metadata()
     {
         Ok(v) => v,
-        Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+        Err(e) => return Err(Error::Bpx(ErrorDomain::Installer, e))
     };
     let json = build_package_info(&obj)?;
     if let Err(e) = fs::write(&folder.join("package-info.json"), json::stringify(json))
@@ -145,28 +209,97 @@ fn unpack_bpx(file: &Path, folder: &Path) -> Result<()>
     }
     if let Err(e) = decoder.unpack(&folder)
     {
-        return Err(Error::Io(ErrorDomain::Installer, e));
+        if let bpx::Error::UnsafePath(v) = &e
+        {
+            return Err(Error::Generic(ErrorDomain::Installer, format!("Refusing to install hostile package {}: {}", file.display(), v)));
+        }
+        return Err(Error::Bpx(ErrorDomain::Installer, e));
     }
     return Ok(());
 }
 
-fn install_dependency(dep: &Dependency, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>) -> Result<()>
+pub fn find_matching_package(registry: &mut Box<dyn PackageRegistry>, name: &str, req: &VersionReq) -> Result<Option<Package>>
+{
+    if req.is_any()
+    {
+        return registry.find_latest(name);
+    }
+    if let Some(version) = req.get_exact()
+    {
+        return registry.find(name, version);
+    }
+    let versions = registry.list_versions(name)?;
+    if let Some(version) = req.find_best(&versions)
+    {
+        return registry.find(name, version);
+    }
+    return Ok(None);
+}
+
+//Returns the registries with the one matching the given base URL first
+fn prefer_registry<'a>(registries: &Vec<&'a RegistryInfo>, base_url: &str) -> Vec<&'a RegistryInfo>
+{
+    let mut res: Vec<&RegistryInfo> = registries.iter().filter(|v| v.base_url == base_url).cloned().collect();
+    for v in registries
+    {
+        if v.base_url != base_url
+        {
+            res.push(v);
+        }
+    }
+    return res;
+}
+
+fn remove_dependency(profilemgr: &ProfileManager, name: &str) -> Result<()>
+{
+    let package_dir = profilemgr.get_toolchain_path().join(Path::new(name));
+    if package_dir.exists()
+    {
+        if let Err(e) = fs::remove_dir_all(&package_dir)
+        {
+            return Err(Error::Io(ErrorDomain::Installer, e));
+        }
+    }
+    return Ok(());
+}
+
+//Which version of a dependency install_dependency should fetch
+#[derive(Copy, Clone)]
+enum VersionSelector<'a>
+{
+    Matching(&'a VersionReq), //The highest version satisfying the requirement
+    Exact(&'a str) //This exact version string, as already resolved (ex: from fpkg.lock)
+}
+
+fn find_selected_package(registry: &mut Box<dyn PackageRegistry>, name: &str, selector: VersionSelector) -> Result<Option<Package>>
+{
+    return match selector
+    {
+        VersionSelector::Matching(req) => find_matching_package(registry, name, req),
+        VersionSelector::Exact(version) => registry.find(name, version)
+    };
+}
+
+//Installs the given dependency using the first registry which has a compatible build of the selected version
+//When lock is specified the downloaded build is verified against the hash recorded in fpkg.lock
+fn install_dependency(dep: &Dependency, selector: VersionSelector, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>, lock: Option<&LockedPackage>, locked: bool) -> Result<LockedPackage>
 {
     let profile = profilemgr.get_current()?;
     println!("Installing dependency {} - {}...", &dep.name, &dep.version);
     for registry_info in registries
     {
         let mut registry = open_package_registry(&registry_info)?;
-        if dep.version == "latest"
+        if let Some(pkg) = find_selected_package(&mut registry, &dep.name, selector)?
         {
-            if let Some(pkg) = registry.find_latest(&dep.name)?
+            for file_name in &pkg.files
             {
-                for file_name in &pkg.files
+                if check_file_name_match(&profile, &file_name)
                 {
-                    if check_file_name_match(&profile, &file_name)
+                    //TODO: Implement profile based verification to ensure package compatibility
+                    let folder = profilemgr.get_toolchain_path().join(Path::new(&dep.name));
+                    let mut attempt = 1;
+                    let (mut builds, hash) = loop
                     {
-                        //TODO: Implement profile based verification to ensure package compatibility
-                        let folder = profilemgr.get_toolchain_path().join(Path::new(&dep.name));
                         if !folder.exists()
                         {
                             if let Err(e) = fs::create_dir(&folder)
@@ -175,17 +308,375 @@ fn install_dependency(dep: &Dependency, profilemgr: &ProfileManager, registries:
                             }
                         }
                         registry.download(&folder, &pkg, &file_name)?;
-                        unpack_bpx(&folder.join(Path::new(&file_name)), &folder)?;
-                        println!("Installed dependency {} - {}", &dep.name, &dep.version);
-                        return Ok(());
-                    }
+                        let hash = hash_file(&folder.join(Path::new(&file_name)))?;
+                        let mut builds = HashMap::new();
+                        if let Some(entry) = lock
+                        {
+                            match entry.builds.get(file_name)
+                            {
+                                //A damaged download does not match either; fetch it again before blaming the registry
+                                Some(h) if h != &hash && attempt < MAX_DOWNLOAD_ATTEMPTS =>
+                                {
+                                    eprintln!("WARNING: {} does not match fpkg.lock, downloading it again...", &file_name);
+                                    remove_dependency(profilemgr, &dep.name)?;
+                                    attempt += 1;
+                                    continue;
+                                },
+                                Some(h) if h != &hash =>
+                                {
+                                    remove_dependency(profilemgr, &dep.name)?;
+                                    return Err(Error::Generic(ErrorDomain::Installer, format!("Hash mismatch for {} - {} ({}): the package has changed since fpkg.lock was written", &dep.name, &pkg.version, &file_name)));
+                                },
+                                None if locked =>
+                                {
+                                    remove_dependency(profilemgr, &dep.name)?;
+                                    return Err(Error::Generic(ErrorDomain::Installer, format!("fpkg.lock is out of date: no build {} recorded for {} - {}", &file_name, &dep.name, &pkg.version)));
+                                },
+                                _ => ()
+                            };
+                            builds = entry.builds.clone();
+                        }
+                        match unpack_bpx(&folder.join(Path::new(&file_name)), &folder)
+                        {
+                            Ok(()) => break (builds, hash),
+                            //The download may have been damaged in transit; try again from a clean folder
+                            Err(Error::Bpx(_, e)) if e.is_corruption() && attempt < MAX_DOWNLOAD_ATTEMPTS =>
+                            {
+                                eprintln!("WARNING: {} is corrupted ({}), downloading it again...", &file_name, e);
+                                remove_dependency(profilemgr, &dep.name)?;
+                                attempt += 1;
+                            },
+                            Err(e) =>
+                            {
+                                remove_dependency(profilemgr, &dep.name)?;
+                                return Err(e);
+                            }
+                        };
+                    };
+                    builds.insert(file_name.clone(), hash);
+                    println!("Installed dependency {} - {}", &dep.name, &pkg.version);
+                    return Ok(LockedPackage
+                    {
+                        name: dep.name.clone(),
+                        requirement: dep.version.clone(),
+                        version: pkg.version.clone(),
+                        registry: registry_info.base_url.clone(),
+                        builds: builds
+                    });
                 }
-                return Err(Error::Generic(ErrorDomain::Installer, format!("The dependency {} - {} is not compatible with your system", &dep.name, &dep.version)));
+            }
+            return Err(Error::Generic(ErrorDomain::Installer, format!("The dependency {} - {} is not compatible with your system", &dep.name, &pkg.version)));
+        }
+    }
+    return match selector
+    {
+        VersionSelector::Matching(req) if req.is_any() => Err(Error::Generic(ErrorDomain::Installer, format!("Could not find dependency {} in any registry", &dep.name))),
+        VersionSelector::Matching(_) => Err(Error::Generic(ErrorDomain::Installer, format!("Could not find a version of dependency {} matching {} in any registry", &dep.name, &dep.version))),
+        VersionSelector::Exact(version) => Err(Error::Generic(ErrorDomain::Installer, format!("Could not find version {} of dependency {} in any registry", version, &dep.name)))
+    };
+}
+
+//Controls how fpkg.lock is used while installing dependencies
+#[derive(Copy, Clone)]
+enum LockMode<'a>
+{
+    Normal, //Reuse locked versions and record newly resolved ones
+    Locked, //Fail if fpkg.lock is missing or out of date
+    Refresh(&'a [String]) //Re-resolve the given dependencies (all when empty) and record the new versions
+}
+
+fn get_installed_version(profilemgr: &ProfileManager, name: &str) -> Result<Option<String>>
+{
+    let path = profilemgr.get_toolchain_path().join(Path::new(name)).join("package-info.json");
+    if !path.exists()
+    {
+        return Ok(None);
+    }
+    let mut map = HashMap::new();
+    read_property_map(&path, &mut map)?;
+    return Ok(map.remove("Version"));
+}
+
+//Re-queries the registries for the newest version matching the declared requirement and replaces the install if outdated
+fn update_dependency(dep: &Dependency, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>, lockfile: &mut Lockfile) -> Result<()>
+{
+    let req = VersionReq::parse(&dep.version)?;
+    let mut latest = None;
+    for registry_info in registries
+    {
+        let mut registry = open_package_registry(&registry_info)?;
+        if let Some(pkg) = find_matching_package(&mut registry, &dep.name, &req)?
+        {
+            latest = Some(pkg.version);
+            break;
+        }
+    }
+    let latest = match latest
+    {
+        Some(v) => v,
+        None => return Err(Error::Generic(ErrorDomain::Installer, format!("Could not find a version of dependency {} matching {} in any registry", &dep.name, &dep.version)))
+    };
+    let current = get_installed_version(profilemgr, &dep.name)?;
+    let up_to_date = match lockfile.get(&dep.name)
+    {
+        Some(entry) => entry.requirement == dep.version && entry.version == latest,
+        None => false
+    };
+    if up_to_date && current.as_deref() == Some(latest.as_str())
+    {
+        println!("Dependency {} is up to date ({})", &dep.name, &latest);
+        return Ok(());
+    }
+    remove_dependency(profilemgr, &dep.name)?;
+    let res = install_dependency(dep, VersionSelector::Exact(&latest), profilemgr, registries, None, false)?;
+    println!("Updated dependency {}: {} -> {}", &dep.name, current.as_deref().unwrap_or("none"), &res.version);
+    lockfile.set(res);
+    return Ok(());
+}
+
+//Returns true if the locked requirement is at least as strict as the given one (the locked version then matches both)
+//This keeps a version picked for several requirements locked when only some of them are seen at first
+fn is_requirement_locked(entry: &LockedPackage, requirement: &str) -> bool
+{
+    if entry.requirement == requirement
+    {
+        return true;
+    }
+    let locked = split_requirement(&entry.requirement);
+    return split_requirement(requirement).iter().all(|v| *v == "latest" || *v == "*" || locked.contains(v));
+}
+
+//Builds the lock entry of an already installed dependency from the registry providing its installed build
+//Returns None if no registry provides it anymore
+fn lock_installed_dependency(dep: &Dependency, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>) -> Result<Option<LockedPackage>>
+{
+    let version = match get_installed_version(profilemgr, &dep.name)?
+    {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    let profile = profilemgr.get_current()?;
+    let folder = profilemgr.get_toolchain_path().join(Path::new(&dep.name));
+    for registry_info in registries
+    {
+        let mut registry = open_package_registry(&registry_info)?;
+        if let Some(pkg) = registry.find(&dep.name, &version)?
+        {
+            let mut builds = HashMap::new();
+            for file_name in &pkg.files
+            {
+                let path = folder.join(Path::new(&file_name));
+                if check_file_name_match(&profile, &file_name) && path.exists()
+                {
+                    builds.insert(file_name.clone(), hash_file(&path)?);
+                }
+            }
+            if !builds.is_empty()
+            {
+                return Ok(Some(LockedPackage
+                {
+                    name: dep.name.clone(),
+                    requirement: dep.version.clone(),
+                    version: version,
+                    registry: registry_info.base_url.clone(),
+                    builds: builds
+                }));
+            }
+        }
+    }
+    return Ok(None);
+}
+
+//Makes sure the given dependency is installed, reusing the version pinned in fpkg.lock when the declared requirement has not changed
+fn install_locked_dependency(dep: &Dependency, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>, lockfile: &mut Lockfile, mode: LockMode) -> Result<()>
+{
+    if let LockMode::Refresh(names) = mode
+    {
+        if names.is_empty() || names.contains(&dep.name)
+        {
+            return update_dependency(dep, profilemgr, registries, lockfile);
+        }
+    }
+    let locked = matches!(mode, LockMode::Locked);
+    let entry = match lockfile.get(&dep.name)
+    {
+        Some(v) if is_requirement_locked(v, &dep.version) => Some(v.clone()),
+        _ => None
+    };
+    match entry
+    {
+        Some(entry) =>
+        {
+            let pinned = Dependency
+            {
+                name: dep.name.clone(),
+                version: entry.version.clone()
+            };
+            if !is_dependency_installed(&pinned, profilemgr)?
+            {
+                let res = install_dependency(dep, VersionSelector::Exact(&entry.version), profilemgr, &prefer_registry(registries, &entry.registry), Some(&entry), locked)?;
+                if !locked
+                {
+                    lockfile.set(res);
+                }
+            }
+        },
+        None =>
+        {
+            if locked
+            {
+                return Err(Error::Generic(ErrorDomain::Installer, format!("fpkg.lock is out of date: dependency {} - {} is not locked", &dep.name, &dep.version)));
+            }
+            //An install from before fpkg.lock existed (or from an older requirement) may still be good enough
+            if is_dependency_installed(dep, profilemgr)?
+            {
+                if let Some(res) = lock_installed_dependency(dep, profilemgr, registries)?
+                {
+                    println!("Locked installed dependency {} - {}", &dep.name, &res.version);
+                    lockfile.set(res);
+                    return Ok(());
+                }
+            }
+            //Re-install in order to know which registry and build is used
+            remove_dependency(profilemgr, &dep.name)?;
+            let req = VersionReq::parse(&dep.version)?;
+            let res = install_dependency(dep, VersionSelector::Matching(&req), profilemgr, registries, None, false)?;
+            lockfile.set(res);
+        }
+    };
+    return Ok(());
+}
+
+struct ResolvedDependency
+{
+    requirement: String,
+    version: String
+}
+
+//Splits a version requirement into its comma separated parts (ex: ">=1.2, <2" => [">=1.2", "<2"])
+fn split_requirement(requirement: &str) -> Vec<&str>
+{
+    return requirement.split(',').map(|v| v.trim()).collect();
+}
+
+//Builds a single requirement which only accepts versions matching all the given requirements
+fn combine_requirements(requirements: &[(String, String)]) -> String
+{
+    let mut parts: Vec<&str> = Vec::new();
+    for (requirement, _) in requirements
+    {
+        for part in split_requirement(requirement)
+        {
+            if part != "latest" && part != "*" && !parts.contains(&part)
+            {
+                parts.push(part);
+            }
+        }
+    }
+    if parts.is_empty()
+    {
+        return String::from("latest");
+    }
+    return parts.join(", ");
+}
+
+fn report_version_conflict(name: &str, requirements: &[(String, String)]) -> Error
+{
+    let mut report = format!("Version conflict for dependency {}: no version matches all requirements", name);
+    for (requirement, required_by) in requirements
+    {
+        report.push_str(&format!("\n\t\t{} requires {} {}", required_by, name, requirement));
+    }
+    return Error::Generic(ErrorDomain::Installer, report);
+}
+
+//Makes sure at least one registry provides a version matching all the given requirements
+fn check_requirements_compatible(name: &str, requirements: &[(String, String)], registries: &Vec<&RegistryInfo>) -> Result<()>
+{
+    let req = match VersionReq::parse(&combine_requirements(requirements))
+    {
+        Ok(v) => v,
+        Err(_) => return Err(report_version_conflict(name, requirements))
+    };
+    for registry_info in registries
+    {
+        let mut registry = open_package_registry(&registry_info)?;
+        if find_matching_package(&mut registry, name, &req)?.is_some()
+        {
+            return Ok(());
+        }
+    }
+    return Err(report_version_conflict(name, requirements));
+}
+
+//Walks the dependency tree once (breadth first), installing each dependency with a version matching all its known requirements
+//Returns None when a requirement rules out a version which has already been picked; the walk must then start again
+fn resolve_dependency_tree(deps: &[Dependency], project: &str, requirements: &mut HashMap<String, Vec<(String, String)>>, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>, lockfile: &mut Lockfile, mode: LockMode) -> Result<Option<Vec<Dependency>>>
+{
+    let mut queue: VecDeque<(Dependency, String)> = deps.iter().map(|v| (Dependency { name: v.name.clone(), version: v.version.clone() }, String::from(project))).collect();
+    let mut resolved: Vec<(String, ResolvedDependency)> = Vec::new();
+
+    while let Some((dep, required_by)) = queue.pop_front()
+    {
+        ensure_valid_dependency_name(&required_by, &dep.name)?;
+        let known = requirements.entry(dep.name.clone()).or_default();
+        let requirement = (dep.version.clone(), required_by.clone());
+        if !known.contains(&requirement)
+        {
+            known.push(requirement);
+        }
+        if let Some((_, res)) = resolved.iter().find(|(name, _)| name == &dep.name)
+        {
+            if !VersionReq::parse(&dep.version)?.matches_str(&res.version)
+            {
+                check_requirements_compatible(&dep.name, &requirements[&dep.name], registries)?;
+                return Ok(None);
             }
             continue;
         }
+        let combined = Dependency
+        {
+            name: dep.name.clone(),
+            version: combine_requirements(&requirements[&dep.name])
+        };
+        if requirements[&dep.name].len() > 1
+        {
+            check_requirements_compatible(&dep.name, &requirements[&dep.name], registries)?;
+        }
+        install_locked_dependency(&combined, profilemgr, registries, lockfile, mode)?;
+        let info = read_package_info(profilemgr, &dep.name)?;
+        let chain = format!("{} -> {}", &required_by, &dep.name);
+        for sub in read_package_dependencies(&info)?
+        {
+            queue.push_back((sub, chain.clone()));
+        }
+        resolved.push((dep.name, ResolvedDependency
+        {
+            requirement: combined.version,
+            version: info["Version"].to_string()
+        }));
+    }
+    return Ok(Some(resolved.into_iter().map(|(name, res)| Dependency
+    {
+        name: name,
+        version: res.requirement
+    }).collect()));
+}
+
+//Installs the given dependencies and all dependencies recorded in their package metadata
+//Every requirement on a dependency is taken into account before picking its version
+//Returns the list of all dependencies which have been installed with their resolved requirement
+fn install_dependency_tree(deps: Vec<Dependency>, project: &str, profilemgr: &ProfileManager, registries: &Vec<&RegistryInfo>, lockfile: &mut Lockfile, mode: LockMode) -> Result<Vec<Dependency>>
+{
+    //Requirements (with the chain of packages requiring them) only grow from one walk to the next so this always ends
+    let mut requirements: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    loop
+    {
+        if let Some(resolved) = resolve_dependency_tree(&deps, project, &mut requirements, profilemgr, registries, lockfile, mode)?
+        {
+            return Ok(resolved);
+        }
     }
-    return Err(Error::Generic(ErrorDomain::Installer, format!("Could not find dependency {} in any registry", &dep.name)));
 }
 
 fn is_dependency_installed(dep: &Dependency, profilemgr: &ProfileManager) -> Result<bool>
@@ -211,7 +702,7 @@ fn is_dependency_installed(dep: &Dependency, profilemgr: &ProfileManager) -> Res
         }
         return Ok(false);
     }
-    if dep.version != "latest" && &map["Version"] != &dep.version
+    if !VersionReq::parse(&dep.version)?.matches_str(&map["Version"])
     {
         //Package is corrupted clear directory
         if let Err(e) = fs::remove_dir_all(&package_dir)
@@ -223,6 +714,106 @@ fn is_dependency_installed(dep: &Dependency, profilemgr: &ProfileManager) -> Res
     return Ok(true);
 }
 
+pub fn read_package_info(profilemgr: &ProfileManager, name: &str) -> Result<json::JsonValue>
+{
+    let path = profilemgr.get_toolchain_path().join(Path::new(name)).join("package-info.json");
+    let res = match fs::read_to_string(&path)
+    {
+        Ok(v) => v,
+        Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+    };
+    return match json::parse(&res)
+    {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::Generic(ErrorDomain::Installer, format!("Error parsing json: {}", e)))
+    };
+}
+
+//What the install function of a project declared during the last fpkg install
+pub struct DeclaredDependencies
+{
+    pub subprojects: Vec<String>,
+    pub dependencies: Vec<Dependency>,
+    pub generator: Option<String>
+}
+
+//Records what the install function declared so that other commands do not need to run it again
+fn write_declared_dependencies(profilemgr: &ProfileManager, subprojects: &[String], deps: &[Dependency], generator: Option<&str>) -> Result<()>
+{
+    let mut subdirs = json::JsonValue::new_array();
+    for path in subprojects
+    {
+        let _ = subdirs.push(json::JsonValue::String(path.clone()));
+    }
+    let mut arr = json::JsonValue::new_array();
+    for dep in deps
+    {
+        let mut obj = json::JsonValue::new_object();
+        obj["Name"] = json::JsonValue::String(dep.name.clone());
+        obj["Version"] = json::JsonValue::String(dep.version.clone());
+        let _ = arr.push(obj);
+    }
+    let mut obj = json::JsonValue::new_object();
+    obj["Subprojects"] = subdirs;
+    obj["Dependencies"] = arr;
+    if let Some(name) = generator
+    {
+        obj["Generator"] = json::JsonValue::String(String::from(name));
+    }
+    if let Err(e) = fs::write(profilemgr.get_toolchain_path().join(DECLARED_DEPENDENCIES_FILE), json::stringify(obj))
+    {
+        return Err(Error::Io(ErrorDomain::Installer, e));
+    }
+    return Ok(());
+}
+
+//Returns what was recorded by the last fpkg install or None if it has not been run for the current toolchain
+pub fn read_declared_dependencies(profilemgr: &ProfileManager) -> Result<Option<DeclaredDependencies>>
+{
+    let path = profilemgr.get_toolchain_path().join(DECLARED_DEPENDENCIES_FILE);
+    if !path.exists()
+    {
+        return Ok(None);
+    }
+    let res = match fs::read_to_string(&path)
+    {
+        Ok(v) => v,
+        Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+    };
+    let json = match json::parse(&res)
+    {
+        Ok(v) => v,
+        Err(e) => return Err(Error::Generic(ErrorDomain::Installer, format!("Error parsing json: {}", e)))
+    };
+    let subprojects = json["Subprojects"].members().filter_map(|v| v.as_str()).map(String::from).collect();
+    return Ok(Some(DeclaredDependencies
+    {
+        subprojects: subprojects,
+        dependencies: read_package_dependencies(&json)?,
+        generator: json["Generator"].as_str().map(String::from)
+    }));
+}
+
+//Refuses the whole package if any of its dependency names could escape the toolchain folder
+pub fn read_package_dependencies(info: &json::JsonValue) -> Result<Vec<Dependency>>
+{
+    let package = info["Name"].as_str().unwrap_or("project");
+    let mut deps = Vec::new();
+    for dep in info["Dependencies"].members()
+    {
+        if let (Some(name), Some(version)) = (dep["Name"].as_str(), dep["Version"].as_str())
+        {
+            ensure_valid_dependency_name(package, name)?;
+            deps.push(Dependency
+            {
+                name: String::from(name),
+                version: String::from(version)
+            });
+        }
+    }
+    return Ok(deps);
+}
+
 fn list_configurations(path: &Path) -> io::Result<Vec<String>>
 {
     let mut configs = Vec::new();
@@ -297,6 +888,29 @@ fn call_generator_lib(package_dir: &Path, package_name: &str, generator: &mut Bo
     return Ok(());
 }
 
+fn open_generator(profilemgr: &ProfileManager, generator_name: Option<String>) -> Result<Box<dyn BuildGenerator>>
+{
+    return match generator_name
+    {
+        None =>
+        {
+            match create_generator("noop", profilemgr.get_base_path(), profilemgr.get_toolchain())?
+            {
+                Some(v) => Ok(v),
+                None => Err(Error::Generic(ErrorDomain::Installer, String::from("Missing base noop generator")))
+            }
+        },
+        Some(name) =>
+        {
+            match create_generator(&name, profilemgr.get_base_path(), profilemgr.get_toolchain())?
+            {
+                Some(v) => Ok(v),
+                None => Err(Error::Generic(ErrorDomain::Installer, format!("No generator named {} found", name)))
+            }
+        }
+    };
+}
+
 fn call_generator(profilemgr: &ProfileManager, dep: &Dependency, generator: &mut Box<dyn BuildGenerator>) -> Result<()>
 {
     let package_dir = profilemgr.get_toolchain_path().join(Path::new(&dep.name));
@@ -314,10 +928,12 @@ fn call_generator(profilemgr: &ProfileManager, dep: &Dependency, generator: &mut
     return Ok(());
 }
 
-fn install_sub_directory(path: &Path, toolchain: Option<&str>) -> Result<Vec<String>>
+//Returns the list of sub-projects to install next and the names of all dependencies of this project
+fn install_sub_directory(path: &Path, toolchain: Option<&str>, mode: LockMode) -> Result<(Vec<String>, Vec<String>)>
 {
     let settings = Settings::new()?;
     let mut res = Vec::new();
+    let mut names = Vec::new();
     let mut profilemgr = ProfileManager::new(path)?;
     let registries = settings.get_registries();
     let mut file = LuaFile::new();
@@ -327,6 +943,7 @@ fn install_sub_directory(path: &Path, toolchain: Option<&str>) -> Result<Vec<Str
         None => "host"
     };
     file.open_libs(path)?;
+    let mut lockfile = Lockfile::load(path)?;
     let path = Path::new(path).join("fpkg.lua");
     file.open(&path)?;
     if !profilemgr.exists() || profilemgr.get_toolchain() != toolchain
@@ -339,50 +956,38 @@ fn install_sub_directory(path: &Path, toolchain: Option<&str>) -> Result<Vec<Str
         profilemgr.install(toolchain, props)?;
     }
     let profile = profilemgr.get_current()?;
+    let project = file.read_table()?.name;
     if file.has_func_install()
     {
         let (subprojects, deps, generator_name) = file.func_install(&profile)?;
+        write_declared_dependencies(&profilemgr, &subprojects, &deps, generator_name.as_deref())?;
         for path in subprojects
         {
             res.push(path);
         }
-        let mut generator = match generator_name
-        {
-            None =>
-            {
-                match create_generator("noop", profilemgr.get_base_path(), profilemgr.get_toolchain())?
-                {
-                    Some(v) => v,
-                    None => return Err(Error::Generic(ErrorDomain::Installer, String::from("Missing base noop generator")))
-                }
-            },
-            Some(name) =>
-            {
-                match create_generator(&name, profilemgr.get_base_path(), profilemgr.get_toolchain())?
-                {
-                    Some(v) => v,
-                    None => return Err(Error::Generic(ErrorDomain::Installer, format!("No generator named {} found", name)))
-                }
-            }
-        };
-        for dep in deps
+        let mut generator = open_generator(&profilemgr, generator_name)?;
+        let resolved = install_dependency_tree(deps, &project, &profilemgr, &registries, &mut lockfile, mode)?;
+        for dep in resolved
         {
-            if !is_dependency_installed(&dep, &profilemgr)?
-            {
-                install_dependency(&dep, &profilemgr, &registries)?;
-            }
+            names.push(dep.name.clone());
             call_generator(&profilemgr, &dep, &mut generator)?;
             if file.has_func_dep_installed()
             {
                 file.func_dep_installed(&dep, &profile)?;
             }
         }
+        let removed = lockfile.retain(&names);
+        if matches!(mode, LockMode::Locked) && !removed.is_empty()
+        {
+            return Err(Error::Generic(ErrorDomain::Installer, format!("fpkg.lock is out of date: {} no longer declared", removed.join(", "))));
+        }
+        lockfile.save()?;
         generator.generate()?;
     }
-    return Ok(res);
+    return Ok((res, names));
 }
 
-fn check_is_valid_project_dir(path: &Path) -> Result<()>
+pub fn check_is_valid_project_dir(path: &Path) -> Result<()>
 {
     let builder = builder::find_builder(&path);
     if builder.is_none() {
@@ -391,18 +996,223 @@ fn check_is_valid_project_dir(path: &Path) -> Result<()>
     return Ok(());
 }
 
-pub fn install(toolchain: Option<&str>) -> Result<()>
+fn install_all(toolchain: Option<&str>, mode: LockMode) -> Result<Vec<String>>
 {
     let mut directories: Vec<String> = Vec::new();
+    let mut names: Vec<String> = Vec::new();
     directories.push(String::from("."));
     while let Some(dir) = directories.pop()
     {
         check_is_valid_project_dir(Path::new(&dir))?;
-        let subdirs = install_sub_directory(Path::new(&dir), toolchain)?;
+        let (subdirs, deps) = install_sub_directory(Path::new(&dir), toolchain, mode)?;
         for v in subdirs
         {
             directories.push(v);
         }
+        names.extend(deps);
     }
+    return Ok(names);
+}
+
+pub fn install(toolchain: Option<&str>, locked: bool) -> Result<()>
+{
+    let mode = match locked
+    {
+        true => LockMode::Locked,
+        false => LockMode::Normal
+    };
+    install_all(toolchain, mode)?;
+    return Ok(());
+}
+
+pub fn update(toolchain: Option<&str>, names: Vec<String>) -> Result<()>
+{
+    let installed = install_all(toolchain, LockMode::Refresh(&names))?;
+    for name in &names
+    {
+        if !installed.contains(name)
+        {
+            return Err(Error::Generic(ErrorDomain::Installer, format!("No dependency named {} in the current project", name)));
+        }
+    }
+    return Ok(());
+}
+
+pub fn list_installed_packages(profilemgr: &ProfileManager) -> Result<Vec<String>>
+{
+    let mut names = Vec::new();
+    let entries = match fs::read_dir(profilemgr.get_toolchain_path())
+    {
+        Ok(v) => v,
+        Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+    };
+    for entry in entries
+    {
+        let entry = match entry
+        {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Io(ErrorDomain::Installer, e))
+        };
+        if entry.path().join("package-info.json").exists()
+        {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    return Ok(names);
+}
+
+pub fn remove(toolchain: Option<&str>, name: &str) -> Result<()>
+{
+    let path = Path::new(".");
+    check_is_valid_project_dir(path)?;
+    let mut profilemgr = ProfileManager::new(path)?;
+    if let Some(t) = toolchain
+    {
+        profilemgr.load(t)?;
+    }
+    if !profilemgr.exists()
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, String::from("Unable to load project profile; did you forget to run fpkg install?")));
+    }
+    if !list_installed_packages(&profilemgr)?.iter().any(|v| v == name)
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, format!("Dependency {} is not installed", name)));
+    }
+    //Only fpkg install runs the install function; use what it recorded
+    let (declared, generator_name) = match read_declared_dependencies(&profilemgr)?
+    {
+        Some(v) => (v.dependencies.iter().any(|v| v.name == name), v.generator),
+        None => (false, None)
+    };
+    remove_dependency(&profilemgr, name)?;
+    println!("Removed dependency {}", name);
+    let mut generator = open_generator(&profilemgr, generator_name)?;
+    let mut required_by = Vec::new();
+    for other in list_installed_packages(&profilemgr)?
+    {
+        let info = read_package_info(&profilemgr, &other)?;
+        if read_package_dependencies(&info)?.iter().any(|v| v.name == name)
+        {
+            required_by.push(other.clone());
+        }
+        let dep = Dependency
+        {
+            name: other,
+            version: String::from(info["Version"].as_str().unwrap_or(""))
+        };
+        call_generator(&profilemgr, &dep, &mut generator)?;
+    }
+    generator.generate()?;
+    if declared
+    {
+        eprintln!("Warning: {} is still declared in fpkg.lua and will be re-installed by the next fpkg install", name);
+    }
+    else if !required_by.is_empty()
+    {
+        eprintln!("Warning: {} is still required by {} and will be re-installed by the next fpkg install", name, required_by.join(", "));
+    }
+    else
+    {
+        let mut lockfile = Lockfile::load(path)?;
+        lockfile.remove(name);
+        lockfile.save()?;
+    }
+    return Ok(());
+}
+
+fn ensure_valid_toolchain_name(toolchain: &str) -> Result<()>
+{
+    //Refuse anything which could escape the .fpkg folder
+    if toolchain.is_empty() || toolchain == "." || toolchain == ".."
+        || toolchain.contains(['/', '\\', ':', '\0'])
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, format!("Invalid toolchain name '{}'", toolchain)));
+    }
+    return Ok(());
+}
+
+pub fn clean(toolchain: Option<&str>, all: bool) -> Result<()>
+{
+    let path = Path::new(".");
+    check_is_valid_project_dir(path)?;
+    let base = path.join(".fpkg");
+    let target = match all
+    {
+        true => base.clone(),
+        false =>
+        {
+            let toolchain = toolchain.unwrap_or("host");
+            ensure_valid_toolchain_name(toolchain)?;
+            base.join(toolchain)
+        }
+    };
+    let metadata = match fs::symlink_metadata(&target)
+    {
+        Ok(v) => v,
+        Err(_) =>
+        {
+            println!("Nothing to clean");
+            return Ok(());
+        }
+    };
+    //Never follow a symbolic link out of the project
+    let res = match metadata.file_type().is_dir()
+    {
+        true => fs::remove_dir_all(&target),
+        false => fs::remove_file(&target)
+    };
+    if let Err(e) = res
+    {
+        return Err(Error::Io(ErrorDomain::Installer, e));
+    }
+    println!("Removed {}", target.display());
     return Ok(());
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn read_crafted_package_info(content: &str) -> json::JsonValue
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("package-info.json");
+        fs::write(&path, content).unwrap();
+        return json::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn package_dependencies()
+    {
+        let info = read_crafted_package_info(r#"{"Name":"bar","Version":"1.0.0","Dependencies":[{"Name":"foo","Version":"^1.0"},{"Name":"zlib-ng_2.x","Version":"2.0.0"}]}"#);
+        let deps = match read_package_dependencies(&info)
+        {
+            Ok(v) => v,
+            Err(_) => panic!("valid dependency names should have been accepted")
+        };
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].name, "foo");
+        assert_eq!(deps[0].version, "^1.0");
+        assert_eq!(deps[1].name, "zlib-ng_2.x");
+    }
+
+    #[test]
+    fn package_dependencies_hostile()
+    {
+        for name in &["../../..", "..", ".", "/etc", "foo/../..", "C:\\Windows", ""]
+        {
+            let mut info = read_crafted_package_info(r#"{"Name":"evil","Version":"1.0.0","Dependencies":[{"Name":"foo","Version":"1.0.0"}]}"#);
+            let mut dep = json::JsonValue::new_object();
+            dep["Name"] = json::JsonValue::String(String::from(*name));
+            dep["Version"] = json::JsonValue::String(String::from("1.0.0"));
+            info["Dependencies"].push(dep).unwrap();
+            match read_package_dependencies(&info)
+            {
+                Err(Error::Generic(ErrorDomain::Installer, msg)) => assert!(msg.contains("evil") && msg.contains(name)),
+                _ => panic!("dependency name '{}' should have been refused", name)
+            };
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+use json::JsonValue;
+
+use crate::common::Result;
+use crate::common::Error;
+use crate::common::ErrorDomain;
+use crate::installer::read_package_info;
+use crate::installer::read_package_dependencies;
+use crate::installer::read_declared_dependencies;
+use crate::installer::list_installed_packages;
+use crate::luaengine::LuaFile;
+use crate::luaengine::Dependency;
+use crate::profile::ProfileManager;
+
+fn open_profile(path: &Path, toolchain: Option<&str>) -> Result<ProfileManager>
+{
+    let mut profilemgr = ProfileManager::new(path)?;
+    if let Some(t) = toolchain
+    {
+        profilemgr.load(t)?;
+    }
+    if !profilemgr.exists()
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, format!("Unable to load profile for project {}; did you forget to run fpkg install?", path.display())));
+    }
+    return Ok(profilemgr);
+}
+
+fn describe_package(info: &JsonValue) -> String
+{
+    return format!("{} - {} ({}, {} {})", info["Name"], info["Version"], info["Type"], info["CompilerName"], info["CompilerVersion"]);
+}
+
+//Copies the properties of an installed package which are useful to scripts
+fn package_summary(info: &JsonValue) -> JsonValue
+{
+    let mut obj = JsonValue::new_object();
+    for key in &["Name", "Version", "Type", "Platform", "Architecture", "CompilerName", "CompilerVersion"]
+    {
+        obj[*key] = info[*key].clone();
+    }
+    return obj;
+}
+
+fn list_installed(profilemgr: &ProfileManager) -> Result<Vec<JsonValue>>
+{
+    let mut res = Vec::new();
+    for name in list_installed_packages(profilemgr)?
+    {
+        res.push(read_package_info(profilemgr, &name)?);
+    }
+    return Ok(res);
+}
+
+pub fn list(toolchain: Option<&str>, json: bool) -> Result<()>
+{
+    let profilemgr = open_profile(Path::new("."), toolchain)?;
+    let packages = list_installed(&profilemgr)?;
+    if json
+    {
+        let mut arr = JsonValue::new_array();
+        for info in &packages
+        {
+            let _ = arr.push(package_summary(info));
+        }
+        println!("{}", arr.pretty(4));
+        return Ok(());
+    }
+    if packages.is_empty()
+    {
+        println!("No packages installed for toolchain {}", profilemgr.get_toolchain());
+    }
+    for info in &packages
+    {
+        println!("{}", describe_package(info));
+    }
+    return Ok(());
+}
+
+//Builds the node of an installed dependency along with the dependencies recorded in its package-info.json
+fn build_dependency_node(profilemgr: &ProfileManager, dep: &Dependency, chain: &mut Vec<String>) -> Result<JsonValue>
+{
+    let mut node;
+    if !profilemgr.get_toolchain_path().join(Path::new(&dep.name)).join("package-info.json").exists()
+    {
+        node = JsonValue::new_object();
+        node["Name"] = JsonValue::String(dep.name.clone());
+        node["Requirement"] = JsonValue::String(dep.version.clone());
+        node["Installed"] = JsonValue::Boolean(false);
+        return Ok(node);
+    }
+    let info = read_package_info(profilemgr, &dep.name)?;
+    node = package_summary(&info);
+    node["Requirement"] = JsonValue::String(dep.version.clone());
+    node["Installed"] = JsonValue::Boolean(true);
+    let mut children = JsonValue::new_array();
+    //A cycle would have been reported by fpkg install; just stop walking it here
+    if !chain.contains(&dep.name)
+    {
+        chain.push(dep.name.clone());
+        for sub in read_package_dependencies(&info)?
+        {
+            let _ = children.push(build_dependency_node(profilemgr, &sub, chain)?);
+        }
+        chain.pop();
+    }
+    node["Dependencies"] = children;
+    return Ok(node);
+}
+
+fn build_project_node(path: &Path, toolchain: Option<&str>) -> Result<JsonValue>
+{
+    let profilemgr = open_profile(path, toolchain)?;
+    let mut file = LuaFile::new();
+    file.open_libs(path)?;
+    file.open(&path.join("fpkg.lua"))?;
+    let mut node = JsonValue::new_object();
+    node["Project"] = JsonValue::String(file.read_table()?.name);
+    node["Path"] = JsonValue::String(path.to_string_lossy().into_owned());
+    let mut deps = JsonValue::new_array();
+    let mut subprojects = JsonValue::new_array();
+    //Only fpkg install runs the install function; use what it recorded
+    if let Some(declared) = read_declared_dependencies(&profilemgr)?
+    {
+        let mut chain = Vec::new();
+        for dep in &declared.dependencies
+        {
+            let _ = deps.push(build_dependency_node(&profilemgr, dep, &mut chain)?);
+        }
+        for dir in declared.subprojects
+        {
+            let _ = subprojects.push(build_project_node(Path::new(&dir), toolchain)?);
+        }
+    }
+    node["Dependencies"] = deps;
+    node["Subprojects"] = subprojects;
+    return Ok(node);
+}
+
+fn print_dependency_nodes(nodes: &JsonValue, indent: &str)
+{
+    let count = nodes.len();
+    for (i, node) in nodes.members().enumerate()
+    {
+        let (branch, next) = match i + 1 == count
+        {
+            true => ("`-- ", "    "),
+            false => ("|-- ", "|   ")
+        };
+        if node["Installed"].as_bool() == Some(true)
+        {
+            println!("{}{}{}", indent, branch, describe_package(node));
+        }
+        else
+        {
+            println!("{}{}{} - {} (not installed)", indent, branch, node["Name"], node["Requirement"]);
+        }
+        print_dependency_nodes(&node["Dependencies"], &format!("{}{}", indent, next));
+    }
+}
+
+fn print_project_node(node: &JsonValue, indent: &str)
+{
+    println!("{}{} ({})", indent, node["Project"], node["Path"]);
+    print_dependency_nodes(&node["Dependencies"], indent);
+    for sub in node["Subprojects"].members()
+    {
+        print_project_node(sub, &format!("{}    ", indent));
+    }
+}
+
+pub fn tree(toolchain: Option<&str>, json: bool) -> Result<()>
+{
+    let root = build_project_node(Path::new("."), toolchain)?;
+    if json
+    {
+        println!("{}", root.pretty(4));
+        return Ok(());
+    }
+    print_project_node(&root, "");
+    return Ok(());
+}
@@ -0,0 +1,393 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::boxed::Box;
+use std::string::String;
+use std::path::Path;
+use std::path::PathBuf;
+use std::fs;
+use std::io;
+
+use crate::common::Result;
+use crate::common::Error;
+use crate::common::ErrorDomain;
+use crate::settings::RegistryInfo;
+use crate::registry::PackageRegistry;
+use crate::registry::RegistryProvider;
+use crate::registry::Package;
+use crate::registry::is_valid_name;
+
+//Registry layout: <root>/<name>/<version>/<build-*.bpx>
+struct LocalRegistry
+{
+    root: PathBuf
+}
+
+fn parse_version(version: &str) -> Option<Vec<u64>>
+{
+    let mut res = Vec::new();
+
+    for component in version.split('.')
+    {
+        match component.parse::<u64>()
+        {
+            Ok(v) => res.push(v),
+            Err(_) => return None
+        }
+    }
+    return Some(res);
+}
+
+fn list_directory_names(path: &Path) -> io::Result<Vec<String>>
+{
+    let mut names = Vec::new();
+
+    for entry in fs::read_dir(path)?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir()
+        {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    return Ok(names);
+}
+
+fn list_build_files(path: &Path) -> io::Result<Vec<String>>
+{
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(path)?
+    {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && name.ends_with(".bpx")
+        {
+            files.push(name);
+        }
+    }
+    files.sort();
+    return Ok(files);
+}
+
+impl LocalRegistry
+{
+    //Every component joined to the registry root comes from the caller so it must not be able to escape it
+    fn join_checked(path: &Path, component: &str) -> Result<PathBuf>
+    {
+        if !is_valid_name(component)
+        {
+            return Err(Error::Generic(ErrorDomain::Registry, format!("The path component {} is not supported by local registries", component)));
+        }
+        return Ok(path.join(Path::new(component)));
+    }
+
+    fn get_name_path(&self, name: &str) -> Result<PathBuf>
+    {
+        return LocalRegistry::join_checked(&self.root, name);
+    }
+
+    fn get_package_path(&self, name: &str, version: &str) -> Result<PathBuf>
+    {
+        return LocalRegistry::join_checked(&self.get_name_path(name)?, version);
+    }
+
+    fn load_package(&self, name: &str, version: &str) -> Result<Package>
+    {
+        let files = match list_build_files(&self.get_package_path(name, version)?)
+        {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Io(ErrorDomain::Registry, e))
+        };
+        return Ok(Package
+        {
+            name: String::from(name),
+            version: String::from(version),
+            files: files
+        });
+    }
+}
+
+impl PackageRegistry for LocalRegistry
+{
+    fn ensure_valid_package(&mut self, package: &Package) -> Result<()>
+    {
+        if !is_valid_name(&package.version)
+        {
+            return Err(Error::Generic(ErrorDomain::Registry, format!("The package version string {} is not supported by local registries", &package.version)));
+        }
+        if !is_valid_name(&package.name)
+        {
+            return Err(Error::Generic(ErrorDomain::Registry, format!("The package name string {} is not supported by local registries", &package.name)));
+        }
+        return Ok(());
+    }
+
+    fn publish(&mut self, package: &Package, file_name: &str, file: &Path) -> Result<()>
+    {
+        let folder = self.get_package_path(&package.name, &package.version)?;
+        let target = LocalRegistry::join_checked(&folder, file_name)?;
+        if target.exists()
+        {
+            return Err(Error::Generic(ErrorDomain::Registry, format!("A package release already exists for the combination {}>{}>{}", &package.name, &package.version, &file_name)));
+        }
+        if let Err(e) = fs::create_dir_all(&folder)
+        {
+            return Err(Error::Io(ErrorDomain::Registry, e));
+        }
+        if let Err(e) = fs::copy(file, &target)
+        {
+            return Err(Error::Io(ErrorDomain::Registry, e));
+        }
+        return Ok(());
+    }
+
+    fn find_latest(&mut self, name: &str) -> Result<Option<Package>>
+    {
+        let versions = self.list_versions(name)?;
+        let mut latest: Option<(Vec<u64>, String)> = None;
+        for version in versions
+        {
+            //Ignore any folder which is not a valid version number; any number of components is accepted (ex: 2021.1.2.3)
+            if let Some(v) = parse_version(&version)
+            {
+                let newer = match &latest
+                {
+                    Some((cur, _)) => &v > cur,
+                    None => true
+                };
+                if newer
+                {
+                    latest = Some((v, version));
+                }
+            }
+        }
+        if let Some((_, version)) = latest
+        {
+            return Ok(Some(self.load_package(name, &version)?));
+        }
+        return Ok(None);
+    }
+
+    fn find(&mut self, name: &str, version: &str) -> Result<Option<Package>>
+    {
+        if !self.get_package_path(name, version)?.is_dir()
+        {
+            return Ok(None);
+        }
+        return Ok(Some(self.load_package(name, version)?));
+    }
+
+    fn list_versions(&mut self, name: &str) -> Result<Vec<String>>
+    {
+        let path = self.get_name_path(name)?;
+        if !path.is_dir()
+        {
+            return Ok(Vec::new());
+        }
+        return match list_directory_names(&path)
+        {
+            Ok(v) => Ok(v.into_iter().filter(|v| is_valid_name(v)).collect()),
+            Err(e) => Err(Error::Io(ErrorDomain::Registry, e))
+        };
+    }
+
+    fn list_packages(&mut self, name: &str) -> Result<Vec<Package>>
+    {
+        let mut res = Vec::new();
+        for version in self.list_versions(name)?
+        {
+            res.push(self.load_package(name, &version)?);
+        }
+        return Ok(res);
+    }
+
+    fn search(&mut self, pattern: &str) -> Result<Vec<Package>>
+    {
+        let mut res = Vec::new();
+        if !self.root.is_dir()
+        {
+            return Ok(res);
+        }
+        let pattern = pattern.to_lowercase();
+        let mut names = match list_directory_names(&self.root)
+        {
+            Ok(v) => v,
+            Err(e) => return Err(Error::Io(ErrorDomain::Registry, e))
+        };
+        names.sort();
+        for name in names
+        {
+            if is_valid_name(&name) && name.to_lowercase().contains(&pattern)
+            {
+                for version in self.list_versions(&name)?
+                {
+                    res.push(Package::new(&name, &version));
+                }
+            }
+        }
+        return Ok(res);
+    }
+
+    fn download(&mut self, target_folder: &Path, package: &Package, file_name: &str) -> Result<()>
+    {
+        let source = LocalRegistry::join_checked(&self.get_package_path(&package.name, &package.version)?, file_name)?;
+        if let Err(e) = fs::copy(&source, LocalRegistry::join_checked(target_folder, file_name)?)
+        {
+            return Err(Error::Io(ErrorDomain::Registry, e));
+        }
+        return Ok(());
+    }
+}
+
+pub struct LocalRegistryProvider
+{
+}
+
+impl LocalRegistryProvider
+{
+    pub fn new() -> Box<LocalRegistryProvider>
+    {
+        return Box::new(LocalRegistryProvider
+        {
+        });
+    }
+}
+
+impl RegistryProvider for LocalRegistryProvider
+{
+    fn open(&self, info: &RegistryInfo) -> Result<Box<dyn PackageRegistry>>
+    {
+        //Strip the "local://" prefix; what remains is a native path (ex: /opt/fpkg/ or C:/fpkg/)
+        let root = match info.base_url.strip_prefix("local://")
+        {
+            Some(v) if !v.is_empty() => v,
+            _ => return Err(Error::Generic(ErrorDomain::Settings, format!("Invalid local registry URL '{}': expected local://<path>", &info.base_url)))
+        };
+        return Ok(Box::new(LocalRegistry
+        {
+            root: PathBuf::from(root)
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn open(root: &Path) -> LocalRegistry
+    {
+        return LocalRegistry
+        {
+            root: root.to_path_buf()
+        };
+    }
+
+    fn publish(registry: &mut LocalRegistry, name: &str, version: &str, file_name: &str, content: &str)
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("build.bpx");
+        fs::write(&file, content).unwrap();
+        if registry.publish(&Package::new(name, version), file_name, &file).is_err()
+        {
+            panic!("failed to publish package");
+        }
+    }
+
+    fn find(registry: &mut LocalRegistry, name: &str, version: &str) -> Option<Package>
+    {
+        return match registry.find(name, version)
+        {
+            Ok(v) => v,
+            Err(_) => panic!("failed to find package")
+        };
+    }
+
+    #[test]
+    fn publish_find_download()
+    {
+        let root = tempfile::tempdir().unwrap();
+        let mut registry = open(root.path());
+        publish(&mut registry, "foo", "1.0.0", "build-b.bpx", "b");
+        publish(&mut registry, "foo", "1.0.0", "build-a.bpx", "a");
+        publish(&mut registry, "foo", "1.10.0", "build-a.bpx", "c");
+        publish(&mut registry, "foo", "1.2.0", "build-a.bpx", "d");
+        let file = root.path().join("build.bpx");
+        fs::write(&file, "e").unwrap();
+        assert!(registry.publish(&Package::new("foo", "1.0.0"), "build-a.bpx", &file).is_err());
+        let package = find(&mut registry, "foo", "1.0.0").unwrap();
+        assert_eq!(package.files, vec![String::from("build-a.bpx"), String::from("build-b.bpx")]);
+        assert!(find(&mut registry, "foo", "3.0.0").is_none());
+        assert!(find(&mut registry, "bar", "1.0.0").is_none());
+        let latest = match registry.find_latest("foo")
+        {
+            Ok(v) => v.unwrap(),
+            Err(_) => panic!("failed to find latest package")
+        };
+        assert_eq!(latest.version, "1.10.0");
+        assert!(matches!(registry.find_latest("bar"), Ok(None)));
+        let target = tempfile::tempdir().unwrap();
+        if registry.download(target.path(), &package, "build-b.bpx").is_err()
+        {
+            panic!("failed to download package");
+        }
+        assert_eq!(fs::read_to_string(target.path().join("build-b.bpx")).unwrap(), "b");
+    }
+
+    #[test]
+    fn rejected_names()
+    {
+        let root = tempfile::tempdir().unwrap();
+        let mut registry = open(&root.path().join("registry"));
+        publish(&mut registry, "foo", "1.0.0", "build-a.bpx", "a");
+        //A sibling of the registry root which must never be reachable
+        fs::create_dir_all(root.path().join("outside").join("1.0.0")).unwrap();
+        fs::write(root.path().join("outside").join("1.0.0").join("build-a.bpx"), "x").unwrap();
+        for name in &["..", ".", "../outside", "/etc", "a/b", "a\\b", ""]
+        {
+            assert!(registry.find(name, "1.0.0").is_err());
+            assert!(registry.find("foo", name).is_err());
+            assert!(registry.find_latest(name).is_err());
+            assert!(registry.list_versions(name).is_err());
+            assert!(registry.list_packages(name).is_err());
+            let target = tempfile::tempdir().unwrap();
+            assert!(registry.download(target.path(), &Package::new(name, "1.0.0"), "build-a.bpx").is_err());
+            assert!(registry.download(target.path(), &Package::new("foo", name), "build-a.bpx").is_err());
+            assert!(registry.download(target.path(), &Package::new("foo", "1.0.0"), name).is_err());
+            assert!(fs::read_dir(target.path()).unwrap().next().is_none());
+        }
+        let target = tempfile::tempdir().unwrap();
+        assert!(registry.download(target.path(), &Package::new("foo", "1.0.0"), "../../outside/1.0.0/build-a.bpx").is_err());
+        let file = root.path().join("build.bpx");
+        fs::write(&file, "e").unwrap();
+        assert!(registry.publish(&Package::new("..", "1.0.0"), "build-a.bpx", &file).is_err());
+        assert!(registry.publish(&Package::new("foo", "1.0.0"), "../build-a.bpx", &file).is_err());
+        assert!(!root.path().join("registry").join("build-a.bpx").exists());
+    }
+}
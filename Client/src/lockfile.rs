@@ -0,0 +1,372 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::string::String;
+use std::vec::Vec;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use json::JsonValue;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::common::Result;
+use crate::common::Error;
+use crate::common::ErrorDomain;
+
+pub const LOCKFILE_NAME: &str = "fpkg.lock";
+
+#[derive(Clone)]
+pub struct LockedPackage
+{
+    pub name: String,
+    pub requirement: String, //The version string as declared in fpkg.lua
+    pub version: String, //The version the requirement was resolved to
+    pub registry: String, //The base URL of the registry the package was downloaded from
+    pub builds: HashMap<String, String> //Build file name => SHA-256 of the build file
+}
+
+pub struct Lockfile
+{
+    path: PathBuf,
+    packages: Vec<LockedPackage>,
+    modified: bool
+}
+
+fn get_string(val: &JsonValue, key: &str) -> Result<String>
+{
+    return match val[key].as_str()
+    {
+        Some(v) => Ok(String::from(v)),
+        None => Err(Error::Generic(ErrorDomain::Lockfile, format!("Invalid type for '{}' key", key)))
+    };
+}
+
+fn parse_package(val: &JsonValue) -> Result<LockedPackage>
+{
+    let mut builds = HashMap::new();
+    match &val["Builds"]
+    {
+        JsonValue::Object(v) =>
+        {
+            for (file_name, hash) in v.iter()
+            {
+                match hash.as_str()
+                {
+                    Some(h) => builds.insert(String::from(file_name), String::from(h)),
+                    None => return Err(Error::Generic(ErrorDomain::Lockfile, format!("Invalid hash for build {}", file_name)))
+                };
+            }
+        },
+        _ => return Err(Error::Generic(ErrorDomain::Lockfile, String::from("Invalid type for 'Builds' key")))
+    };
+    return Ok(LockedPackage
+    {
+        name: get_string(val, "Name")?,
+        requirement: get_string(val, "Requirement")?,
+        version: get_string(val, "Version")?,
+        registry: get_string(val, "Registry")?,
+        builds: builds
+    });
+}
+
+impl Lockfile
+{
+    //Loads the lock file from the given project directory; returns an empty lock file if none exists yet
+    pub fn load(project_dir: &Path) -> Result<Lockfile>
+    {
+        let path = project_dir.join(LOCKFILE_NAME);
+        let mut packages = Vec::new();
+        if path.exists()
+        {
+            let res = match fs::read_to_string(&path)
+            {
+                Ok(v) => v,
+                Err(e) => return Err(Error::Io(ErrorDomain::Lockfile, e))
+            };
+            let json = match json::parse(&res)
+            {
+                Ok(v) => v,
+                Err(e) => return Err(Error::Generic(ErrorDomain::Lockfile, format!("Error parsing json: {}", e)))
+            };
+            match &json["Packages"]
+            {
+                JsonValue::Array(v) =>
+                {
+                    for val in v
+                    {
+                        packages.push(parse_package(val)?);
+                    }
+                },
+                _ => return Err(Error::Generic(ErrorDomain::Lockfile, String::from("Invalid type for 'Packages' key")))
+            };
+        }
+        return Ok(Lockfile
+        {
+            path: path,
+            packages: packages,
+            modified: false
+        });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LockedPackage>
+    {
+        return self.packages.iter().find(|v| v.name == name);
+    }
+
+    pub fn set(&mut self, package: LockedPackage)
+    {
+        self.packages.retain(|v| v.name != package.name);
+        self.packages.push(package);
+        self.modified = true;
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool
+    {
+        let len = self.packages.len();
+        self.packages.retain(|v| v.name != name);
+        if self.packages.len() != len
+        {
+            self.modified = true;
+            return true;
+        }
+        return false;
+    }
+
+    //Removes all entries which are not part of the given list of dependency names; returns the removed names
+    pub fn retain(&mut self, names: &[String]) -> Vec<String>
+    {
+        let mut removed = Vec::new();
+        for v in &self.packages
+        {
+            if !names.contains(&v.name)
+            {
+                removed.push(v.name.clone());
+            }
+        }
+        if !removed.is_empty()
+        {
+            self.packages.retain(|v| names.contains(&v.name));
+            self.modified = true;
+        }
+        return removed;
+    }
+
+    pub fn save(&mut self) -> Result<()>
+    {
+        if !self.modified
+        {
+            return Ok(());
+        }
+        self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut arr = JsonValue::new_array();
+        for package in &self.packages
+        {
+            let mut builds = JsonValue::new_object();
+            let mut names: Vec<&String> = package.builds.keys().collect();
+            names.sort();
+            for name in names
+            {
+                builds[name.as_str()] = JsonValue::String(package.builds[name].clone());
+            }
+            let mut obj = JsonValue::new_object();
+            obj["Name"] = JsonValue::String(package.name.clone());
+            obj["Requirement"] = JsonValue::String(package.requirement.clone());
+            obj["Version"] = JsonValue::String(package.version.clone());
+            obj["Registry"] = JsonValue::String(package.registry.clone());
+            obj["Builds"] = builds;
+            if let Err(e) = arr.push(obj)
+            {
+                return Err(Error::Generic(ErrorDomain::Lockfile, format!("Error building json: {}", e)));
+            }
+        }
+        let mut json = JsonValue::new_object();
+        json["Packages"] = arr;
+        if let Err(e) = fs::write(&self.path, json.pretty(4))
+        {
+            return Err(Error::Io(ErrorDomain::Lockfile, e));
+        }
+        self.modified = false;
+        return Ok(());
+    }
+}
+
+fn sha256_file(path: &Path) -> io::Result<String>
+{
+    let mut hasher = Sha256::new();
+    let mut buf: [u8; 8192] = [0; 8192];
+    let mut f = File::open(path)?;
+
+    loop
+    {
+        let bytes = f.read(&mut buf)?;
+        if bytes == 0
+        {
+            break;
+        }
+        hasher.update(&buf[0..bytes]);
+    }
+    let mut s = String::new();
+    for b in hasher.finalize()
+    {
+        s.push_str(&format!("{:02x}", b));
+    }
+    return Ok(s);
+}
+
+pub fn hash_file(path: &Path) -> Result<String>
+{
+    return match sha256_file(path)
+    {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::Io(ErrorDomain::Lockfile, e))
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn package(name: &str, version: &str, builds: &[(&str, &str)]) -> LockedPackage
+    {
+        return LockedPackage
+        {
+            name: String::from(name),
+            requirement: format!("^{}", version),
+            version: String::from(version),
+            registry: String::from("local:///tmp/registry/"),
+            builds: builds.iter().map(|(k, v)| (String::from(*k), String::from(*v))).collect()
+        };
+    }
+
+    fn load(dir: &Path) -> Lockfile
+    {
+        return match Lockfile::load(dir)
+        {
+            Ok(v) => v,
+            Err(_) => panic!("failed to load lock file")
+        };
+    }
+
+    fn save(lockfile: &mut Lockfile)
+    {
+        if lockfile.save().is_err()
+        {
+            panic!("failed to save lock file");
+        }
+    }
+
+    #[test]
+    fn load_missing()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut lockfile = load(dir.path());
+        assert!(lockfile.get("foo").is_none());
+        //Nothing changed so nothing should be written
+        save(&mut lockfile);
+        assert!(!dir.path().join(LOCKFILE_NAME).exists());
+    }
+
+    #[test]
+    fn round_trip()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut lockfile = load(dir.path());
+        lockfile.set(package("zlib", "1.2.11", &[("build-b.bpx", "bb"), ("build-a.bpx", "aa")]));
+        lockfile.set(package("bar", "1.0.0", &[("build-a.bpx", "cc")]));
+        lockfile.set(package("foo", "1.0.0", &[]));
+        lockfile.set(package("foo", "1.2.0", &[("build-a.bpx", "dd")]));
+        save(&mut lockfile);
+        let content = fs::read_to_string(dir.path().join(LOCKFILE_NAME)).unwrap();
+        //Packages are sorted by name and builds by file name so that the file diffs cleanly
+        let bar = content.find("\"bar\"").unwrap();
+        let foo = content.find("\"foo\"").unwrap();
+        let zlib = content.find("\"zlib\"").unwrap();
+        assert!(bar < foo && foo < zlib);
+        assert!(content.find("build-a.bpx\": \"aa\"").unwrap() < content.find("build-b.bpx\": \"bb\"").unwrap());
+        let lockfile = load(dir.path());
+        let foo = lockfile.get("foo").unwrap();
+        assert_eq!(foo.version, "1.2.0");
+        assert_eq!(foo.requirement, "^1.2.0");
+        assert_eq!(foo.registry, "local:///tmp/registry/");
+        assert_eq!(foo.builds["build-a.bpx"], "dd");
+        let zlib = lockfile.get("zlib").unwrap();
+        assert_eq!(zlib.builds.len(), 2);
+        assert_eq!(zlib.builds["build-b.bpx"], "bb");
+        assert!(lockfile.get("baz").is_none());
+    }
+
+    #[test]
+    fn remove_retain()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut lockfile = load(dir.path());
+        lockfile.set(package("foo", "1.0.0", &[]));
+        lockfile.set(package("bar", "1.0.0", &[]));
+        lockfile.set(package("zlib", "1.2.11", &[]));
+        save(&mut lockfile);
+        let mut lockfile = load(dir.path());
+        assert!(lockfile.remove("foo"));
+        assert!(!lockfile.remove("foo"));
+        assert_eq!(lockfile.retain(&[String::from("bar"), String::from("baz")]), vec![String::from("zlib")]);
+        assert!(lockfile.retain(&[String::from("bar")]).is_empty());
+        save(&mut lockfile);
+        let lockfile = load(dir.path());
+        assert!(lockfile.get("foo").is_none());
+        assert!(lockfile.get("zlib").is_none());
+        assert!(lockfile.get("bar").is_some());
+    }
+
+    #[test]
+    fn invalid()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(LOCKFILE_NAME), r#"{"Packages":[{"Name":"foo","Version":"1.0.0","Registry":"local:///"}]}"#).unwrap();
+        assert!(Lockfile::load(dir.path()).is_err());
+    }
+
+    #[test]
+    fn hash()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.bpx");
+        fs::write(&path, "abc").unwrap();
+        let hash = match hash_file(&path)
+        {
+            Ok(v) => v,
+            Err(_) => panic!("failed to hash file")
+        };
+        assert_eq!(hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(hash_file(&dir.path().join("missing.bpx")).is_err());
+    }
+}
@@ -140,12 +140,43 @@ pub struct PackageTable
     pub compilers: Option<Vec<Compiler>>
 }
 
+//Optional compression settings returned by the package function (ex: compression = { codec = "zlib", level = 1 })
+pub struct CompressionTable
+{
+    pub codec: Option<String>,
+    pub level: Option<u32>,
+    pub threshold: Option<usize>
+}
+
+impl FromLua<'_> for CompressionTable
+{
+    fn from_lua(val: rlua::Value<'_>, _: rlua::Context<'_>) -> std::result::Result<Self, rlua::Error>
+    {
+        if let rlua::Value::Table(table) = val
+        {
+            return Ok(CompressionTable
+            {
+                codec: table.get("codec")?,
+                level: table.get("level")?,
+                threshold: table.get("threshold")?
+            });
+        }
+        return Err(rlua::Error::FromLuaConversionError
+        {
+            from: "CompressionTable",
+            to: "CompressionTable",
+            message: Some(String::from("Could not load table"))
+        });
+    }
+}
+
 pub struct Target
 {
     pub typefkjh: String,
     pub includes: Option<Vec<ConfiguredTarget>>,
     pub binaries: Option<Vec<ConfiguredTarget>>,
-    pub content: Option<Vec<String>>
+    pub content: Option<Vec<String>>,
+    pub compression: Option<CompressionTable>
 }
 
 struct ToolchainConfig
@@ -537,13 +568,15 @@ impl LuaFile
                     let bins: Option<Vec<ConfiguredTarget>> = v.get("binaries")?;
                     let incs: Option<Vec<ConfiguredTarget>> = v.get("includes")?;
                     let cnt: Option<Vec<String>> = v.get("content")?;
+                    let compression: Option<CompressionTable> = v.get("compression")?;
 
                     return Ok(Some(Target
                     {
                         typefkjh: typedas,
                         binaries: bins,
                         includes: incs,
-                        content: cnt
+                        content: cnt,
+                        compression: compression
                     }));
                 },
                 None => return Ok(None)
@@ -55,12 +55,16 @@ mod profile;
 mod common;
 mod settings;
 mod resources;
+mod version;
+mod lockfile;
 
 //Commands implementations
 mod installer;
 mod packager;
 mod publisher;
 mod scripts;
+mod query;
+mod listing;
 
 //Lua Engine
 mod luaengine;
@@ -73,6 +77,7 @@ mod lualiblog;
 //Registry implementations
 mod registry;
 mod gitlabregistry;
+mod localregistry;
 
 //Builder implementations
 mod builder;
@@ -112,7 +117,7 @@ impl ResultType for ()
     }
 }
 
-//error_code: 1 = Generic, 2 = Io, 3 = Lua
+//error_code: 1 = Generic, 2 = Io, 3 = Lua, 4 = Bpx
 fn handle_err(domain: common::ErrorDomain, error: String, error_code: i32) -> i32
 {
     eprintln!("In domain: {}", domain);
@@ -131,6 +136,8 @@ fn handle_result<T: ResultType>(res: common::Result<T>) -> i32
             {
                 common::Error::Io(d, v) => handle_err(d, format!("\tAn io error has occured: {}", v), 2),
                 common::Error::Lua(d, v) => handle_err(d, format!("\tA lua error has occured: {}", v), 3),
+                common::Error::Bpx(d, v) if v.is_unsupported() => handle_err(d, format!("\tThis package uses a BPX feature not supported by this version of fpkg: {}", v), 4),
+                common::Error::Bpx(d, v) => handle_err(d, format!("\tA bpx error has occured: {}", v), 4),
                 common::Error::Generic(d, v) => handle_err(d, format!("\tAn error has occured: {}", v), 1)
             };
             return res;
@@ -173,10 +180,52 @@ fn main() {
             (@arg publish: -p --publish "Publish the package.")
             (@arg registry: +takes_value --registry -r "Specify the name of the registry to publish to.")
             (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to install packages for. Defaults to the host toolchain.")
+            (@arg compression: +takes_value -c --compression possible_value[xz zlib store] "Overrides the compression codec (xz, zlib or store). Defaults to the compression table of fpkg.lua or xz.")
+            (@arg level: +takes_value -l --level "Overrides the compression level (0 = fast to 9 = small).")
         )
         (@subcommand install =>
             (about: "Install all required dependencies and SDKs")
             (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to install packages for. Defaults to the host toolchain.")
+            (@arg locked: --locked "Fail if fpkg.lock is missing or out of date instead of updating it.")
+        )
+        (@subcommand update =>
+            (about: "Update installed dependencies to the newest versions allowed by fpkg.lua")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to install packages for. Defaults to the host toolchain.")
+            (@arg names: ... "Names of the dependencies to update. Defaults to all dependencies.")
+        )
+        (@subcommand remove =>
+            (about: "Remove an installed dependency and regenerate the build files")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to remove the package from. Defaults to the host toolchain.")
+            (@arg name: +required "The name of the dependency to remove.")
+        )
+        (@subcommand clean =>
+            (about: "Remove installed packages and profiles")
+            (@arg toolchain: +takes_value -t --toolchain conflicts_with[all] "Specifies the toolchain to clean. Defaults to the host toolchain.")
+            (@arg all: -a --all "Remove all toolchains.")
+        )
+        (@subcommand search =>
+            (about: "Search all configured registries for packages")
+            (@arg pattern: +required "The text to search for in package names.")
+        )
+        (@subcommand info =>
+            (about: "Show available versions and builds of a package")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain used to flag matching builds. Defaults to the host toolchain.")
+            (@arg name: +required "The name of the package.")
+            (@arg version: "Show only the given version of the package.")
+        )
+        (@subcommand outdated =>
+            (about: "Report installed dependencies which have newer versions available; exits with code 10 if any is found")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to check packages for. Defaults to the host toolchain.")
+        )
+        (@subcommand list =>
+            (about: "List packages installed for the current project")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to list packages for. Defaults to the host toolchain.")
+            (@arg json: --json "Print the list as JSON.")
+        )
+        (@subcommand tree =>
+            (about: "Show the dependency tree of the current project and its sub-projects")
+            (@arg toolchain: +takes_value -t --toolchain "Specifies the toolchain to list packages for. Defaults to the host toolchain.")
+            (@arg json: --json "Print the tree as JSON.")
         )
         (@subcommand run =>
             (about: "Run custom lua scripts")
@@ -187,7 +236,44 @@ fn main() {
 
     if let Some(matches) = matches.subcommand_matches("install")
     {
-        std::process::exit(handle_result(installer::install(matches.value_of("toolchain"))));
+        std::process::exit(handle_result(installer::install(matches.value_of("toolchain"), matches.is_present("locked"))));
+    }
+    if let Some(matches) = matches.subcommand_matches("update")
+    {
+        let names = match matches.values_of("names")
+        {
+            Some(v) => v.map(String::from).collect(),
+            None => Vec::new()
+        };
+        std::process::exit(handle_result(installer::update(matches.value_of("toolchain"), names)));
+    }
+    if let Some(matches) = matches.subcommand_matches("remove")
+    {
+        std::process::exit(handle_result(installer::remove(matches.value_of("toolchain"), matches.value_of("name").unwrap())));
+    }
+    if let Some(matches) = matches.subcommand_matches("clean")
+    {
+        std::process::exit(handle_result(installer::clean(matches.value_of("toolchain"), matches.is_present("all"))));
+    }
+    if let Some(matches) = matches.subcommand_matches("search")
+    {
+        std::process::exit(handle_result(query::search(matches.value_of("pattern").unwrap())));
+    }
+    if let Some(matches) = matches.subcommand_matches("info")
+    {
+        std::process::exit(handle_result(query::info(matches.value_of("name").unwrap(), matches.value_of("version"), matches.value_of("toolchain"))));
+    }
+    if let Some(matches) = matches.subcommand_matches("outdated")
+    {
+        std::process::exit(handle_result(query::outdated(matches.value_of("toolchain"))));
+    }
+    if let Some(matches) = matches.subcommand_matches("list")
+    {
+        std::process::exit(handle_result(listing::list(matches.value_of("toolchain"), matches.is_present("json"))));
+    }
+    if let Some(matches) = matches.subcommand_matches("tree")
+    {
+        std::process::exit(handle_result(listing::tree(matches.value_of("toolchain"), matches.is_present("json"))));
     }
     if let Some(matches) = matches.subcommand_matches("build")
     {
@@ -199,7 +285,7 @@ fn main() {
     }
     if let Some(matches) = matches.subcommand_matches("package")
     {
-        let res = handle_result(packager::package(Path::new("./"), matches.value_of("toolchain")));
+        let res = handle_result(packager::package(Path::new("./"), matches.value_of("toolchain"), matches.value_of("compression"), matches.value_of("level")));
         if res != 0
         {
             std::process::exit(res);
@@ -30,18 +30,23 @@ use std::path::Path;
 use std::path::PathBuf;
 use bpx::bpxp;
 use bpx::sd;
+use bpx::section::CompressionMethod;
+use bpx::section::CompressionPolicy;
+use bpx::section::ChecksumMethod;
 use std::io;
 use std::fs::metadata;
 
 use crate::luaengine::LuaFile;
 use crate::luaengine::PackageTable;
 use crate::luaengine::Target;
+use crate::luaengine::CompressionTable;
 use crate::common::Error;
 use crate::common::ErrorDomain;
 use crate::common::Result;
 use crate::profile::ProfileManager;
 use crate::profile::Profile;
 use crate::builder::check_build_configuration;
+use crate::installer::read_declared_dependencies;
 
 pub fn get_pk_file(profile: &Profile) -> String
 {
@@ -59,9 +64,26 @@ pub fn get_pk_file(profile: &Profile) -> String
     return s;
 }
 
-fn new_pk(profile: &Profile) -> io::Result<bpxp::Encoder>
+fn new_pk(profile: &Profile) -> bpx::Result<bpxp::Encoder>
 {
-    return bpxp::Encoder::new(Path::new(&get_pk_file(&profile)));
+    let mut pk = bpxp::Encoder::new(Path::new(&get_pk_file(&profile)))?;
+    //Packages travel through registries and networks; use a checksum which actually catches corruption
+    pk.set_checksum_method(ChecksumMethod::Crc32);
+    //Follow the reproducible builds convention when it is requested: modification times are clamped to SOURCE_DATE_EPOCH
+    //and permissions no longer depend on the umask (755 for directories and executables, 644 otherwise) so that CI runners produce identical packages
+    if let Ok(v) = std::env::var("SOURCE_DATE_EPOCH")
+    {
+        match v.parse::<u64>()
+        {
+            Ok(epoch) =>
+            {
+                pk.max_mtime = Some(epoch);
+                pk.normalize_permissions = true;
+            },
+            Err(_) => eprintln!("WARNING: Ignoring invalid SOURCE_DATE_EPOCH {}", v)
+        };
+    }
+    return Ok(pk);
 }
 
 fn get_vname(cfg: String, subdir: &str, path: &Path) -> io::Result<String>
@@ -98,7 +120,7 @@ fn pack_lib(bpx: &mut bpxp::Encoder, target: &Target, package: &PackageTable) ->
             };
             if let Err(e) = bpx.pack_vname(path, &vname)
             {
-                return Err(Error::Io(ErrorDomain::Packager, e));
+                return Err(Error::Bpx(ErrorDomain::Packager, e));
             }
         }
     }
@@ -115,7 +137,7 @@ fn pack_lib(bpx: &mut bpxp::Encoder, target: &Target, package: &PackageTable) ->
             };
             if let Err(e) = bpx.pack_vname(path, &vname)
             {
-                return Err(Error::Io(ErrorDomain::Packager, e));
+                return Err(Error::Bpx(ErrorDomain::Packager, e));
             }
         }
     }
@@ -130,7 +152,7 @@ fn pack_framework(bpx: &mut bpxp::Encoder, target: &Target) -> Result<()>
         {
             if let Err(e) = bpx.pack(Path::new(&file))
             {
-                return Err(Error::Io(ErrorDomain::Packager, e));
+                return Err(Error::Bpx(ErrorDomain::Packager, e));
             }
         }
     }
@@ -176,8 +198,72 @@ fn set_type_ext(bpx: &mut bpxp::Encoder, profile: &Profile)
     }
 }
 
-pub fn package(path: &Path, toolchain: Option<&str>) -> Result<i32>
+//Records the dependencies declared in the install function so that consumers can install them transitively
+//They are read from what fpkg install recorded as running the install function again could have side effects
+fn build_dependency_list(lua: &LuaFile, profilemgr: &ProfileManager) -> Result<sd::Array>
 {
+    let mut arr = sd::Array::new();
+    if lua.has_func_install()
+    {
+        let deps = match read_declared_dependencies(profilemgr)?
+        {
+            Some(v) => v.dependencies,
+            None => return Err(Error::Generic(ErrorDomain::Packager, String::from("Unable to find the dependencies of the project; did you forget to run fpkg install?")))
+        };
+        for dep in deps
+        {
+            let mut obj = sd::Object::new();
+            obj.set("Name", sd::Value::String(dep.name));
+            obj.set("Version", sd::Value::String(dep.version));
+            obj.add_debug_info();
+            arr.add(sd::Value::Object(obj));
+        }
+    }
+    return Ok(arr);
+}
+
+//Command line options take precedence over the compression table of fpkg.lua
+fn get_compression_policy(table: Option<&CompressionTable>, codec: Option<&str>, level: Option<&str>) -> Result<CompressionPolicy>
+{
+    let codec = match codec
+    {
+        Some(v) => Some(v),
+        None => table.and_then(|v| v.codec.as_deref())
+    };
+    let mut policy = match codec
+    {
+        None | Some("xz") => CompressionPolicy::new(CompressionMethod::Xz),
+        Some("zlib") => CompressionPolicy::new(CompressionMethod::Zlib),
+        Some("store") => CompressionPolicy::store_only(),
+        Some(v) => return Err(Error::Generic(ErrorDomain::Packager, format!("Unknown compression codec {}; expected xz, zlib or store", v)))
+    };
+    policy.level = match level
+    {
+        Some(v) => match v.parse::<u32>()
+        {
+            Ok(l) => Some(l),
+            Err(_) => return Err(Error::Generic(ErrorDomain::Packager, format!("Invalid compression level {}", v)))
+        },
+        None => table.and_then(|v| v.level)
+    };
+    if let Some(l) = policy.level
+    {
+        if l > 9
+        {
+            return Err(Error::Generic(ErrorDomain::Packager, format!("Invalid compression level {}; expected a value between 0 and 9", l)));
+        }
+    }
+    if let Some(threshold) = table.and_then(|v| v.threshold)
+    {
+        policy.threshold = threshold;
+    }
+    return Ok(policy);
+}
+
+pub fn package(path: &Path, toolchain: Option<&str>, codec: Option<&str>, level: Option<&str>) -> Result<i32>
+{
+    //Refuse bad command line options before running anything
+    get_compression_policy(None, codec, level)?;
     let mut profilemgr = ProfileManager::new(path)?;
     if let Some(t) = toolchain
     {
@@ -196,26 +282,32 @@ pub fn package(path: &Path, toolchain: Option<&str>) -> Result<i32>
     println!("Packaging {} - {} ({}) with Lua Engine...", package.name, package.version, package.description);
     if let Some(target) = lua.func_package(&profile)?
     {
+        let policy = get_compression_policy(target.compression.as_ref(), codec, level)?;
         let mut pk = match new_pk(&profile)
         {
             Ok(v) => v,
-            Err(e) => return Err(Error::Io(ErrorDomain::Packager, e))
+            Err(e) => return Err(Error::Bpx(ErrorDomain::Packager, e))
         };
         if target.typefkjh != "Library" && target.typefkjh != "Framework"
         {
             return Err(Error::Generic(ErrorDomain::Packager, format!("Unrecognized package type {}", target.typefkjh)));
         }
+        if let Err(e) = pk.set_compression_policy(policy)
+        {
+            return Err(Error::Bpx(ErrorDomain::Packager, e));
+        }
         let mut obj = sd::Object::new();
         set_type_ext(&mut pk, &profile);
         obj.set("Name", sd::Value::String(package.name.clone()));
         obj.set("Version", sd::Value::String(package.version.clone()));
         obj.set("Description", sd::Value::String(package.description.clone()));
         obj.set("Type", sd::Value::String(String::from(&target.typefkjh)));
+        obj.set("Dependencies", sd::Value::Array(build_dependency_list(&lua, &profilemgr)?));
         profile.fill_structured_data(&mut obj);
         obj.add_debug_info();
         if let Err(e) = pk.add_metadata(&obj)
         {
-            return Err(Error::Io(ErrorDomain::Packager, e));
+            return Err(Error::Bpx(ErrorDomain::Packager, e));
         }
         if target.typefkjh == "Library"
         {
@@ -229,7 +321,7 @@ pub fn package(path: &Path, toolchain: Option<&str>) -> Result<i32>
         }
         if let Err(e) = pk.save()
         {
-            return Err(Error::Io(ErrorDomain::Packager, e));
+            return Err(Error::Bpx(ErrorDomain::Packager, e));
         }
         println!("Successfully generated build package {}", get_pk_file(&profile));
         return Ok(0)
@@ -0,0 +1,352 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::common::Result;
+use crate::common::Error;
+use crate::common::ErrorDomain;
+use crate::installer::check_file_name_match;
+use crate::installer::check_is_valid_project_dir;
+use crate::installer::list_installed_packages;
+use crate::installer::read_package_info;
+use crate::installer::read_package_dependencies;
+use crate::installer::read_declared_dependencies;
+use crate::profile::ProfileManager;
+use crate::registry::open_package_registry;
+use crate::settings::Settings;
+use crate::version::Version;
+use crate::version::VersionReq;
+
+//Orders versions from the newest to the oldest; strings which are not version numbers go last
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering
+{
+    return match (Version::parse(a), Version::parse(b))
+    {
+        (Some(v), Some(v1)) => v1.cmp(&v),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b)
+    };
+}
+
+fn sort_versions(versions: &mut [String])
+{
+    versions.sort_by(|a, b| compare_versions(a, b));
+}
+
+fn describe_build(file_name: &str) -> String
+{
+    //File format: build-Platform-Arch-CompilerName-CompilerVersion.bpx
+    let components = file_name.trim_end_matches(".bpx").split('-').collect::<Vec<&str>>();
+    if components.len() != 5 || components[0] != "build"
+    {
+        return String::from(file_name);
+    }
+    return format!("{} {} {} {}", components[1], components[2], components[3], components[4]);
+}
+
+fn load_profile(toolchain: Option<&str>) -> Result<ProfileManager>
+{
+    let mut profilemgr = ProfileManager::new(Path::new("."))?;
+    if let Some(t) = toolchain
+    {
+        profilemgr.load(t)?;
+    }
+    return Ok(profilemgr);
+}
+
+pub fn search(pattern: &str) -> Result<()>
+{
+    let settings = Settings::new()?;
+    let mut names: Vec<String> = Vec::new();
+    let mut map: HashMap<String, (String, Vec<String>)> = HashMap::new();
+
+    for registry_info in settings.get_registries()
+    {
+        let mut registry = open_package_registry(registry_info)?;
+        let mut found: HashMap<String, Vec<String>> = HashMap::new();
+        for pkg in registry.search(pattern)?
+        {
+            found.entry(pkg.name).or_default().push(pkg.version);
+        }
+        //Registries are listed by priority, the first registry providing a package is the one fpkg install will use
+        for (name, versions) in found
+        {
+            if let Entry::Vacant(e) = map.entry(name.clone())
+            {
+                names.push(name);
+                e.insert((registry_info.base_url.clone(), versions));
+            }
+        }
+    }
+    if names.is_empty()
+    {
+        println!("No package matching '{}' found", pattern);
+        return Ok(());
+    }
+    names.sort();
+    for name in names
+    {
+        let (base_url, versions) = map.get_mut(&name).unwrap();
+        sort_versions(versions);
+        println!("{} - {} ({} version(s)) [{}]", name, versions[0], versions.len(), base_url);
+    }
+    return Ok(());
+}
+
+pub fn info(name: &str, version: Option<&str>, toolchain: Option<&str>) -> Result<()>
+{
+    let settings = Settings::new()?;
+    let profilemgr = load_profile(toolchain)?;
+    let profile = profilemgr.get_current().ok();
+    let mut found = false;
+
+    for registry_info in settings.get_registries()
+    {
+        let mut registry = open_package_registry(registry_info)?;
+        let mut packages = match version
+        {
+            Some(v) => registry.find(name, v)?.into_iter().collect(),
+            None => registry.list_packages(name)?
+        };
+        packages.sort_by(|a, b| compare_versions(&a.version, &b.version));
+        let mut header = false;
+        for pkg in packages
+        {
+            if !header
+            {
+                println!("{} [{}]", name, registry_info.base_url);
+                header = true;
+            }
+            found = true;
+            println!("    {}", pkg.version);
+            if pkg.files.is_empty()
+            {
+                println!("        (no builds)");
+            }
+            for file in &pkg.files
+            {
+                let matches = match profile
+                {
+                    Some(p) => check_file_name_match(p, file),
+                    None => false
+                };
+                if matches
+                {
+                    println!("        {} (matches current profile)", describe_build(file));
+                }
+                else
+                {
+                    println!("        {}", describe_build(file));
+                }
+            }
+        }
+    }
+    if !found
+    {
+        return match version
+        {
+            Some(v) => Err(Error::Generic(ErrorDomain::Registry, format!("Could not find package {} - {} in any registry", name, v))),
+            None => Err(Error::Generic(ErrorDomain::Registry, format!("Could not find package {} in any registry", name)))
+        };
+    }
+    return Ok(());
+}
+
+//Returns true if version b is newer than version a
+//Versions which are not version numbers (ex: 1.0.0-beta) can't be ordered so they are never reported as newer
+fn is_newer(a: &str, b: &str) -> bool
+{
+    return match (Version::parse(a), Version::parse(b))
+    {
+        (Some(v), Some(v1)) => v1 > v,
+        _ => false
+    };
+}
+
+//Exit code of fpkg outdated when at least one dependency is outdated; distinct from the error codes (1 to 4)
+pub const EXIT_OUTDATED: i32 = 10;
+
+struct OutdatedEntry
+{
+    name: String,
+    current: String,
+    wanted: String,
+    latest: String
+}
+
+//Collects the declared requirement of each dependency: the ones recorded by fpkg install first, then the requirements recorded by installed packages
+fn collect_requirements(profilemgr: &ProfileManager, installed: &[String]) -> Result<HashMap<String, String>>
+{
+    let mut map = HashMap::new();
+    if let Some(declared) = read_declared_dependencies(profilemgr)?
+    {
+        for dep in declared.dependencies
+        {
+            map.insert(dep.name, dep.version);
+        }
+    }
+    for name in installed
+    {
+        let info = read_package_info(profilemgr, name)?;
+        for dep in read_package_dependencies(&info)?
+        {
+            map.entry(dep.name).or_insert(dep.version);
+        }
+    }
+    return Ok(map);
+}
+
+fn print_outdated_table(entries: &[OutdatedEntry])
+{
+    let header = ["Package", "Current", "Wanted", "Latest"];
+    let mut widths = [0; 4];
+    let mut rows = vec!(header.iter().map(|v| String::from(*v)).collect::<Vec<String>>());
+    for entry in entries
+    {
+        rows.push(vec!(entry.name.clone(), entry.current.clone(), entry.wanted.clone(), entry.latest.clone()));
+    }
+    for row in &rows
+    {
+        for (i, cell) in row.iter().enumerate()
+        {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+    for row in &rows
+    {
+        println!("{:w0$}  {:w1$}  {:w2$}  {}", row[0], row[1], row[2], row[3], w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+    }
+}
+
+pub fn outdated(toolchain: Option<&str>) -> Result<i32>
+{
+    let path = Path::new(".");
+    check_is_valid_project_dir(path)?;
+    let settings = Settings::new()?;
+    let registries = settings.get_registries();
+    let mut profilemgr = ProfileManager::new(path)?;
+    if let Some(t) = toolchain
+    {
+        profilemgr.load(t)?;
+    }
+    if !profilemgr.exists()
+    {
+        return Err(Error::Generic(ErrorDomain::Installer, String::from("Unable to load project profile; did you forget to run fpkg install?")));
+    }
+    let installed = list_installed_packages(&profilemgr)?;
+    let requirements = collect_requirements(&profilemgr, &installed)?;
+    let mut entries = Vec::new();
+
+    for name in &installed
+    {
+        let info = read_package_info(&profilemgr, name)?;
+        let current = String::from(info["Version"].as_str().unwrap_or("-"));
+        let req = match requirements.get(name)
+        {
+            Some(v) => Some(VersionReq::parse(v)?),
+            None => None
+        };
+        let mut wanted: Option<String> = None;
+        let mut latest: Option<String> = None;
+        for registry_info in &registries
+        {
+            let mut registry = open_package_registry(registry_info)?;
+            //list_versions only returns exact name matches unlike find_latest which may hit other packages on GitLab
+            let versions = registry.list_versions(name)?;
+            let mut sorted = versions.clone();
+            sort_versions(&mut sorted);
+            //Same as fpkg install: the first registry providing a matching version wins
+            if let (None, Some(req)) = (&wanted, &req)
+            {
+                wanted = if req.is_any()
+                {
+                    sorted.first().cloned()
+                }
+                else
+                {
+                    req.find_best(&versions).map(String::from)
+                };
+            }
+            if let Some(version) = sorted.into_iter().next()
+            {
+                let newer = match &latest
+                {
+                    Some(v) => is_newer(v, &version),
+                    None => true
+                };
+                if newer
+                {
+                    latest = Some(version);
+                }
+            }
+        }
+        let wanted = wanted.unwrap_or_else(|| String::from("-"));
+        let latest = latest.unwrap_or_else(|| String::from("-"));
+        if (wanted != "-" && is_newer(&current, &wanted)) || (latest != "-" && is_newer(&current, &latest))
+        {
+            entries.push(OutdatedEntry
+            {
+                name: name.clone(),
+                current: current,
+                wanted: wanted,
+                latest: latest
+            });
+        }
+    }
+    if entries.is_empty()
+    {
+        println!("All dependencies are up to date");
+        return Ok(0);
+    }
+    print_outdated_table(&entries);
+    return Ok(EXIT_OUTDATED);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn newer()
+    {
+        assert!(is_newer("1.2.0", "1.10.0"));
+        assert!(!is_newer("1.10.0", "1.2.0"));
+        assert!(!is_newer("1.2.0", "1.2.0"));
+        //Versions which can't be ordered are never reported as outdated
+        assert!(!is_newer("nightly", "1.0.0"));
+        assert!(!is_newer("1.0.0", "1.0.0-beta"));
+        assert!(!is_newer("1.0.0-rc1", "1.0.0-beta"));
+    }
+}
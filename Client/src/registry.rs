@@ -31,12 +31,14 @@ use std::boxed::Box;
 use std::path::Path;
 use std::string::String;
 use std::vec::Vec;
+use regex::Regex;
 
 use crate::common::Result;
 use crate::common::Error;
 use crate::common::ErrorDomain;
 use crate::settings::RegistryInfo;
 use crate::gitlabregistry::GitLabRegistryProvider;
+use crate::localregistry::LocalRegistryProvider;
 
 pub struct Package
 {
@@ -58,12 +60,23 @@ impl Package
     }
 }
 
+//Package names and versions end up as directory names so they must not be able to escape their parent folder
+pub fn is_valid_name(name: &str) -> bool
+{
+    let re = Regex::new(r"^([a-z]|[A-Z]|\d|\.|-|_)+$").unwrap();
+
+    return re.is_match(name) && name != "." && name != "..";
+}
+
 pub trait PackageRegistry
 {
     fn ensure_valid_package(&mut self, package: &Package) -> Result<()>;
     fn publish(&mut self, package: &Package, file_name: &str, file: &Path) -> Result<()>;
     fn find_latest(&mut self, name: &str) -> Result<Option<Package>>;
     fn find(&mut self, name: &str, version: &str) -> Result<Option<Package>>;
+    fn list_versions(&mut self, name: &str) -> Result<Vec<String>>;
+    fn list_packages(&mut self, name: &str) -> Result<Vec<Package>>; //All versions of a package along with their files
+    fn search(&mut self, pattern: &str) -> Result<Vec<Package>>;
     fn download(&mut self, target_folder: &Path, package: &Package, file_name: &str) -> Result<()>;
 }
 
@@ -87,6 +100,7 @@ fn get_provider_by_scheme(scheme: &str) -> Result<Box<dyn RegistryProvider>>
 
     map.insert("gitlab", GitLabRegistryProvider::new());
     map.insert("gitlab-priv", GitLabRegistryProvider::new());
+    map.insert("local", LocalRegistryProvider::new());
     if map.contains_key(&scheme)
     {
         let obj = map.remove(&scheme).unwrap();
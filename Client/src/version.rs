@@ -0,0 +1,452 @@
+// Copyright (c) 2021, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::string::String;
+use std::vec::Vec;
+use std::fmt;
+
+use crate::common::Result;
+use crate::common::Error;
+use crate::common::ErrorDomain;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version
+{
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64
+}
+
+impl Version
+{
+    pub fn new(major: u64, minor: u64, patch: u64) -> Version
+    {
+        return Version
+        {
+            major: major,
+            minor: minor,
+            patch: patch
+        };
+    }
+
+    //Returns the version and the number of components actually present in the string (ex: 1.2 => 2)
+    fn parse_partial(version: &str) -> Option<(Version, usize)>
+    {
+        let mut components: Vec<u64> = Vec::new();
+
+        for component in version.trim().split('.')
+        {
+            match component.parse::<u64>()
+            {
+                Ok(v) => components.push(v),
+                Err(_) => return None
+            }
+        }
+        if components.len() > 3
+        {
+            return None;
+        }
+        let count = components.len();
+        components.resize(3, 0);
+        return Some((Version::new(components[0], components[1], components[2]), count));
+    }
+
+    pub fn parse(version: &str) -> Option<Version>
+    {
+        return Version::parse_partial(version).map(|(v, _)| v);
+    }
+}
+
+impl fmt::Display for Version
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        return write!(f, "{}.{}.{}", self.major, self.minor, self.patch);
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Operator
+{
+    Exact,
+    GreaterEq,
+    Less
+}
+
+#[derive(Copy, Clone)]
+struct Comparator
+{
+    op: Operator,
+    version: Version
+}
+
+impl Comparator
+{
+    fn matches(&self, version: &Version) -> bool
+    {
+        return match self.op
+        {
+            Operator::Exact => version == &self.version,
+            Operator::GreaterEq => version >= &self.version,
+            Operator::Less => version < &self.version
+        };
+    }
+}
+
+//Returns the first version which is no longer part of the given partial version (ex: 1.2 => 1.3.0)
+fn bump_partial(version: &Version, count: usize) -> Version
+{
+    return match count
+    {
+        1 => Version::new(version.major + 1, 0, 0),
+        2 => Version::new(version.major, version.minor + 1, 0),
+        _ => Version::new(version.major, version.minor, version.patch + 1)
+    };
+}
+
+fn parse_comparator(s: &str, out: &mut Vec<Comparator>) -> Option<()>
+{
+    let (op, rest) = if let Some(rest) = s.strip_prefix(">=")
+    {
+        (">=", rest)
+    }
+    else if let Some(rest) = s.strip_prefix("<=")
+    {
+        ("<=", rest)
+    }
+    else if s.starts_with(['>', '<', '=', '^', '~'])
+    {
+        s.split_at(1)
+    }
+    else
+    {
+        ("", s)
+    };
+    let (version, count) = Version::parse_partial(rest)?;
+    match op
+    {
+        ">=" => out.push(Comparator { op: Operator::GreaterEq, version: version }),
+        ">" => out.push(Comparator { op: Operator::GreaterEq, version: bump_partial(&version, count) }),
+        "<" => out.push(Comparator { op: Operator::Less, version: version }),
+        "<=" => out.push(Comparator { op: Operator::Less, version: bump_partial(&version, count) }),
+        //A plain version inside a combination still pins that exact version
+        "" => out.push(Comparator { op: Operator::Exact, version: version }),
+        "=" =>
+        {
+            if count == 3
+            {
+                out.push(Comparator { op: Operator::Exact, version: version });
+            }
+            else
+            {
+                out.push(Comparator { op: Operator::GreaterEq, version: version });
+                out.push(Comparator { op: Operator::Less, version: bump_partial(&version, count) });
+            }
+        },
+        "~" =>
+        {
+            out.push(Comparator { op: Operator::GreaterEq, version: version });
+            out.push(Comparator { op: Operator::Less, version: bump_partial(&version, std::cmp::min(count, 2)) });
+        },
+        "^" =>
+        {
+            //The left-most non-zero component must not change
+            let upper = if version.major > 0 || count == 1
+            {
+                bump_partial(&version, 1)
+            }
+            else if version.minor > 0 || count == 2
+            {
+                bump_partial(&version, 2)
+            }
+            else
+            {
+                bump_partial(&version, 3)
+            };
+            out.push(Comparator { op: Operator::GreaterEq, version: version });
+            out.push(Comparator { op: Operator::Less, version: upper });
+        },
+        _ => return None
+    }
+    return Some(());
+}
+
+//Returns true if the given string can only be a version pin (no operator, no combination)
+fn is_plain_version(spec: &str) -> bool
+{
+    return !spec.is_empty()
+        && !spec.starts_with(['>', '<', '=', '^', '~', '*'])
+        && !spec.contains(|c: char| c == ',' || c.is_whitespace());
+}
+
+//A version requirement as found in the second argument of addDependency
+//Supported syntax: latest, *, =1.2, >=1.2, >1.2, <2, <=1.4, ~1.2.3, ^1.2 and comma separated combinations (ex: >=1.2, <2)
+//A version without operator (ex: 1.2, 1.2.3, 1.0.0-beta or 2021.1.2.3) is an exact pin; use =1.2 to accept any 1.2.x
+pub struct VersionReq
+{
+    comparators: Vec<Comparator>,
+    exact: Option<String>
+}
+
+impl VersionReq
+{
+    pub fn any() -> VersionReq
+    {
+        return VersionReq
+        {
+            comparators: Vec::new(),
+            exact: None
+        };
+    }
+
+    pub fn parse(spec: &str) -> Result<VersionReq>
+    {
+        let spec = spec.trim();
+        let mut comparators = Vec::new();
+
+        if spec == "latest" || spec == "*"
+        {
+            return Ok(VersionReq::any());
+        }
+        if is_plain_version(spec)
+        {
+            return Ok(VersionReq
+            {
+                comparators: Vec::new(),
+                exact: Some(String::from(spec))
+            });
+        }
+        for part in spec.split(',')
+        {
+            let part = part.trim();
+            if part.is_empty() || parse_comparator(part, &mut comparators).is_none()
+            {
+                return Err(Error::Generic(ErrorDomain::Installer, format!("Invalid version requirement '{}'", spec)));
+            }
+        }
+        return Ok(VersionReq
+        {
+            comparators: comparators,
+            exact: None
+        });
+    }
+
+    //Returns true if this requirement accepts any version (latest)
+    pub fn is_any(&self) -> bool
+    {
+        return self.comparators.is_empty() && self.exact.is_none();
+    }
+
+    //Returns the version string if this requirement pins a single version
+    pub fn get_exact(&self) -> Option<&str>
+    {
+        return self.exact.as_deref();
+    }
+
+    pub fn matches(&self, version: &Version) -> bool
+    {
+        if self.comparators.is_empty()
+        {
+            if let Some(exact) = &self.exact
+            {
+                return exact == &version.to_string();
+            }
+        }
+        return self.comparators.iter().all(|c| c.matches(version));
+    }
+
+    pub fn matches_str(&self, version: &str) -> bool
+    {
+        if let Some(exact) = &self.exact
+        {
+            return exact == version;
+        }
+        return match Version::parse(version)
+        {
+            Some(v) => self.matches(&v),
+            None => false
+        };
+    }
+
+    //Picks the highest version string in the given list which satisfies this requirement
+    pub fn find_best<'a>(&self, versions: &'a Vec<String>) -> Option<&'a str>
+    {
+        let mut best: Option<(Version, &str)> = None;
+
+        if let Some(exact) = &self.exact
+        {
+            return versions.iter().find(|v| *v == exact).map(|v| v.as_str());
+        }
+        for s in versions
+        {
+            if let Some(v) = Version::parse(s)
+            {
+                let newer = match &best
+                {
+                    Some((cur, _)) => &v > cur,
+                    None => true
+                };
+                if newer && self.matches(&v)
+                {
+                    best = Some((v, s));
+                }
+            }
+        }
+        return best.map(|(_, s)| s);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn req(spec: &str) -> VersionReq
+    {
+        return VersionReq::parse(spec).ok().unwrap();
+    }
+
+    #[test]
+    fn caret()
+    {
+        let r = req("^1.2");
+        assert!(r.matches_str("1.2.0"));
+        assert!(r.matches_str("1.9.3"));
+        assert!(!r.matches_str("1.1.9"));
+        assert!(!r.matches_str("2.0.0"));
+        let r = req("^0.2.3");
+        assert!(r.matches_str("0.2.9"));
+        assert!(!r.matches_str("0.3.0"));
+        let r = req("^0.0.3");
+        assert!(r.matches_str("0.0.3"));
+        assert!(!r.matches_str("0.0.4"));
+    }
+
+    #[test]
+    fn tilde()
+    {
+        let r = req("~1.2.3");
+        assert!(r.matches_str("1.2.3"));
+        assert!(r.matches_str("1.2.9"));
+        assert!(!r.matches_str("1.2.2"));
+        assert!(!r.matches_str("1.3.0"));
+        let r = req("~1");
+        assert!(r.matches_str("1.9.0"));
+        assert!(!r.matches_str("2.0.0"));
+    }
+
+    #[test]
+    fn range()
+    {
+        let r = req(">=1.2, <2");
+        assert!(r.matches_str("1.2.0"));
+        assert!(r.matches_str("1.99.99"));
+        assert!(!r.matches_str("1.1.0"));
+        assert!(!r.matches_str("2.0.0"));
+        let r = req(">1.2,<=1.4");
+        assert!(!r.matches_str("1.2.5"));
+        assert!(r.matches_str("1.3.0"));
+        assert!(r.matches_str("1.4.7"));
+        assert!(!r.matches_str("1.5.0"));
+    }
+
+    #[test]
+    fn any()
+    {
+        assert!(req("*").is_any());
+        assert!(req("latest").is_any());
+        assert!(req(" latest ").is_any());
+        assert!(!req("1").is_any());
+    }
+
+    #[test]
+    fn partial()
+    {
+        let r = req("=1.2");
+        assert!(r.get_exact().is_none());
+        assert!(r.matches_str("1.2.0"));
+        assert!(r.matches_str("1.2.7"));
+        assert!(!r.matches_str("1.3.0"));
+        let r = req("=1");
+        assert!(r.matches_str("1.5.0"));
+        assert!(!r.matches_str("2.0.0"));
+    }
+
+    #[test]
+    fn exact()
+    {
+        let r = req("1.2.3");
+        assert_eq!(r.get_exact(), Some("1.2.3"));
+        assert!(r.matches_str("1.2.3"));
+        assert!(!r.matches_str("1.2.4"));
+    }
+
+    #[test]
+    fn exact_pins()
+    {
+        for spec in ["1.2", "1.0.0-beta", "2021.1.2.3"]
+        {
+            let r = req(spec);
+            assert!(!r.is_any());
+            assert_eq!(r.get_exact(), Some(spec));
+            assert!(r.matches_str(spec));
+            assert!(!r.matches_str("1.0.0"));
+        }
+        //Known-good pins must not pick up newer patch releases
+        let r = req("1.2");
+        assert!(!r.matches_str("1.2.0"));
+        assert!(!r.matches_str("1.2.5"));
+        let versions = vec![String::from("1.2"), String::from("1.2.5")];
+        assert_eq!(r.find_best(&versions), Some("1.2"));
+        assert_eq!(req("1.2").find_best(&vec![String::from("1.2.5")]), None);
+        //Inside a combination a plain version still pins that exact version
+        let r = req("1.2.3, ^1");
+        assert!(r.matches_str("1.2.3"));
+        assert!(!r.matches_str("1.2.4"));
+    }
+
+    #[test]
+    fn invalid()
+    {
+        assert!(VersionReq::parse(">=1.0.0-beta").is_err());
+        assert!(VersionReq::parse("^abc").is_err());
+        assert!(VersionReq::parse("1.0,").is_err());
+        assert!(VersionReq::parse("1 2").is_err());
+    }
+
+    #[test]
+    fn find_best()
+    {
+        let versions = vec![String::from("1.0.0"), String::from("1.4.2"), String::from("2.0.0"), String::from("nightly"), String::from("1.10.0")];
+        assert_eq!(req("^1").find_best(&versions), Some("1.10.0"));
+        assert_eq!(req("~1.4").find_best(&versions), Some("1.4.2"));
+        assert_eq!(req("*").find_best(&versions), Some("2.0.0"));
+        assert_eq!(req("nightly").find_best(&versions), Some("nightly"));
+        assert_eq!(req("^3").find_best(&versions), None);
+    }
+}